@@ -6,10 +6,10 @@ use std::iter;
 
 // These benchmark tests are derived from the ones in https://github.com/burntsushi/aho-corasick
 
-static HAYSTACK_RANDOM: &'static str = include_str!("random.txt");
+static HAYSTACK_RANDOM: &str = include_str!("random.txt");
 
 fn haystack_same(letter: char) -> String {
-    iter::repeat(letter).take(10000).collect()
+    iter::repeat_n(letter, 10000).collect()
 }
 
 // A naive multi-pattern search.
@@ -37,7 +37,7 @@ macro_rules! basic_benches {
             #![allow(unused_imports)]
             use super::{haystack_same, naive_find, HAYSTACK_RANDOM};
             use dnfa::automaton::Automaton;
-            use dnfa::dfa::{DDFA, DFA};
+            use dnfa::dfa::{SafeDDFA, DDFA, DFA};
             use dnfa::nfa::NFA;
 
             use test::Bencher;
@@ -171,6 +171,16 @@ basic_benches!(nfa_boxed, |b: &mut Bencher,
     b.iter(|| assert!(Automaton::find(nfa, haystack.as_bytes()).next().is_none()));
 });
 
+basic_benches!(nfa_prefilter, |b: &mut Bencher,
+                               needles: Vec<&str>,
+                               haystack: &str| {
+    b.bytes = haystack.len() as u64;
+    let mut nfa = NFA::from_dictionary(needles);
+    nfa.ignore_prefixes();
+
+    b.iter(|| assert!(nfa.find_with_prefilter(haystack.as_bytes()).next().is_none()));
+});
+
 basic_benches!(dnfa_direct, |b: &mut Bencher,
                              needles: Vec<&str>,
                              haystack: &str| {
@@ -246,3 +256,25 @@ basic_benches!(ddfa_boxed, |b: &mut Bencher,
 
     b.iter(|| assert!(Automaton::find(ddfa, haystack.as_bytes()).next().is_none()));
 });
+
+basic_benches!(safe_ddfa_direct, |b: &mut Bencher,
+                                  needles: Vec<&str>,
+                                  haystack: &str| {
+    b.bytes = haystack.len() as u64;
+    let mut nfa = NFA::from_dictionary(needles);
+    nfa.ignore_prefixes();
+    let safe_ddfa = nfa.powerset_construction().into_dfa().unwrap().into_safe_ddfa();
+
+    b.iter(|| assert!(safe_ddfa.find(haystack.as_bytes()).next().is_none()));
+});
+
+basic_benches!(safe_ddfa_boxed, |b: &mut Bencher,
+                                 needles: Vec<&str>,
+                                 haystack: &str| {
+    b.bytes = haystack.len() as u64;
+    let mut nfa = NFA::from_dictionary(needles);
+    nfa.ignore_prefixes();
+    let safe_ddfa: &SafeDDFA = &nfa.powerset_construction().into_dfa().unwrap().into_safe_ddfa();
+
+    b.iter(|| assert!(Automaton::find(safe_ddfa, haystack.as_bytes()).next().is_none()));
+});
@@ -0,0 +1,16 @@
+// A deterministic set of pseudo-random lowercase words, so runs are comparable
+pub fn random_dictionary(size: usize) -> Vec<Vec<u8>> {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) as usize
+    };
+    (0..size)
+        .map(|_| {
+            let len = 4 + next() % 9;
+            (0..len).map(|_| b'a' + (next() % 26) as u8).collect()
+        })
+        .collect()
+}
@@ -0,0 +1,80 @@
+#![feature(test)]
+
+extern crate test;
+
+mod common;
+
+use common::random_dictionary;
+use dnfa::automaton::Automaton;
+use dnfa::lazy_dfa::LazyDFA;
+use dnfa::nfa::NFA;
+
+use test::Bencher;
+
+#[bench]
+fn from_dictionary_100k(b: &mut Bencher) {
+    let dict = random_dictionary(100_000);
+    b.iter(|| NFA::from_dictionary(&dict));
+}
+
+#[cfg(feature = "rayon")]
+#[bench]
+fn from_dictionary_parallel_100k(b: &mut Bencher) {
+    let dict = random_dictionary(100_000);
+    b.iter(|| NFA::from_dictionary_parallel(&dict));
+}
+
+// Includes building the NFA, since add_depth_map only does its work once
+#[bench]
+fn add_depth_map_10k_states(b: &mut Bencher) {
+    // About 8 bytes per word, with little sharing
+    let dict = random_dictionary(1_300);
+    b.iter(|| {
+        let mut nfa = NFA::from_dictionary(&dict);
+        nfa.add_depth_map();
+        nfa
+    });
+}
+
+static SHERLOCK_NEEDLES: &[&str] = &[
+    "Sherlock", "Holmes", "Watson", "Irene", "Adler", "John", "Baker",
+];
+
+#[bench]
+fn powerset_small(b: &mut Bencher) {
+    let mut nfa = NFA::from_dictionary(SHERLOCK_NEEDLES);
+    nfa.ignore_prefixes();
+    b.iter(|| nfa.powerset_construction_small());
+}
+
+#[bench]
+fn powerset_general(b: &mut Bencher) {
+    let mut nfa = NFA::from_dictionary(SHERLOCK_NEEDLES);
+    nfa.ignore_prefixes();
+    b.iter(|| nfa.powerset_construction_general());
+}
+
+static SHORT_HAYSTACK: &[u8] = b"the quick brown fox jumps over the lazy dog";
+
+// Searching a short haystack for a large dictionary only visits a few of the DFA states, so the
+//  lazy DFA builds far fewer states than the powerset construction
+#[bench]
+fn lazy_dfa_1k_short_haystack(b: &mut Bencher) {
+    let mut nfa = NFA::from_dictionary(random_dictionary(1_000));
+    nfa.ignore_prefixes();
+    b.iter(|| {
+        let lazy = LazyDFA::new(&nfa);
+        let matches = lazy.find(SHORT_HAYSTACK).count();
+        (matches, lazy.cached_state_count())
+    });
+}
+
+#[bench]
+fn powerset_1k_short_haystack(b: &mut Bencher) {
+    let mut nfa = NFA::from_dictionary(random_dictionary(1_000));
+    nfa.ignore_prefixes();
+    b.iter(|| {
+        let dnfa = nfa.powerset_construction();
+        dnfa.find(SHORT_HAYSTACK).count()
+    });
+}
@@ -0,0 +1,69 @@
+#![feature(test)]
+
+extern crate test;
+
+mod common;
+
+use common::random_dictionary;
+use dnfa::nfa::NFA;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use test::Bencher;
+
+// Run these with and without `--features smallvec` to compare the NFA representations, and
+//  with `-- --nocapture` to see the sizes
+
+// Keeps track of the bytes that are allocated, to measure the size of an NFA
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// The bytes that `f` leaves allocated, e.g. in the automaton it returns
+fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let res = f();
+    (res, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+// Reports the heap size of the NFA once, and times building it
+#[bench]
+fn nfa_memory_10k(b: &mut Bencher) {
+    let dict = random_dictionary(10_000);
+    let (nfa, bytes) = allocated_by(|| NFA::from_dictionary(&dict));
+    eprintln!(
+        "NFA of {} states for 10k words: {} bytes on the heap",
+        nfa.state_count(),
+        bytes
+    );
+    b.iter(|| NFA::from_dictionary(&dict));
+}
+
+#[bench]
+fn powerset_construction_200(b: &mut Bencher) {
+    let mut nfa = NFA::from_dictionary(random_dictionary(200));
+    nfa.ignore_prefixes();
+    let (dnfa, bytes) = allocated_by(|| nfa.powerset_construction());
+    eprintln!(
+        "Powerset construction of {} states for 200 words: {} bytes on the heap",
+        dnfa.state_count(),
+        bytes
+    );
+    b.iter(|| nfa.powerset_construction());
+}
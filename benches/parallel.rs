@@ -0,0 +1,78 @@
+#![feature(test)]
+
+extern crate test;
+
+use dnfa::automaton::{search_parallel, search_parallel_count, Automaton};
+use dnfa::dfa::SafeDDFA;
+use dnfa::nfa::NFA;
+
+use test::Bencher;
+
+static HAYSTACK_SHERLOCK: &str = include_str!("sherlock.txt");
+
+// 500 haystacks of 10 KB, cut from the Sherlock text and wrapping around at its end
+fn haystacks() -> Vec<Vec<u8>> {
+    let text = HAYSTACK_SHERLOCK.as_bytes();
+    (0..500)
+        .map(|i| {
+            let start = (i * 10_000) % text.len();
+            text.iter()
+                .cycle()
+                .skip(start)
+                .take(10_000)
+                .cloned()
+                .collect()
+        })
+        .collect()
+}
+
+fn safe_ddfa() -> SafeDDFA {
+    let needles = vec![
+        "Sherlock", "Holmes", "Watson", "Irene", "Adler", "John", "Baker",
+    ];
+    let mut nfa = NFA::from_dictionary(needles);
+    nfa.ignore_prefixes();
+    nfa.powerset_construction()
+        .into_dfa()
+        .unwrap()
+        .into_safe_ddfa()
+}
+
+#[bench]
+fn sequential_500x10k(b: &mut Bencher) {
+    let haystacks = haystacks();
+    let aut = safe_ddfa();
+    b.bytes = 500 * 10_000;
+    b.iter(|| {
+        haystacks
+            .iter()
+            .map(|haystack| aut.find(haystack).collect())
+            .collect::<Vec<Vec<_>>>()
+    });
+}
+
+#[bench]
+fn parallel_500x10k(b: &mut Bencher) {
+    let haystacks = haystacks();
+    let aut = safe_ddfa();
+    let sequential: Vec<Vec<_>> = haystacks
+        .iter()
+        .map(|haystack| aut.find(haystack).collect())
+        .collect();
+    assert_eq!(search_parallel(&aut, &haystacks), sequential);
+    b.bytes = 500 * 10_000;
+    b.iter(|| search_parallel(&aut, &haystacks));
+}
+
+#[bench]
+fn parallel_count_500x10k(b: &mut Bencher) {
+    let haystacks = haystacks();
+    let aut = safe_ddfa();
+    let sequential: usize = haystacks
+        .iter()
+        .map(|haystack| aut.find(haystack).count())
+        .sum();
+    assert_eq!(search_parallel_count(&aut, &haystacks), sequential);
+    b.bytes = 500 * 10_000;
+    b.iter(|| search_parallel_count(&aut, &haystacks));
+}
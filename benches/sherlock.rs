@@ -4,7 +4,7 @@ extern crate test;
 
 // These benchmark tests are derived from the ones in https://github.com/burntsushi/aho-corasick
 
-static HAYSTACK_SHERLOCK: &'static str = include_str!("sherlock.txt");
+static HAYSTACK_SHERLOCK: &str = include_str!("sherlock.txt");
 
 macro_rules! sherlock_benches {
     ($prefix:ident, $bench_match_count:expr) => {
@@ -12,7 +12,7 @@ macro_rules! sherlock_benches {
             #![allow(unused_imports)]
             use super::HAYSTACK_SHERLOCK;
             use dnfa::automaton::Automaton;
-            use dnfa::dfa::{DDFA, DFA};
+            use dnfa::dfa::{SafeDDFA, DDFA, DFA};
             use dnfa::nfa::NFA;
 
             use test::Bencher;
@@ -200,3 +200,55 @@ sherlock_benches!(
         b.iter(|| assert_eq!(count, Automaton::find(ddfa, haystack.as_bytes()).count()));
     }
 );
+
+sherlock_benches!(
+    safe_ddfa_direct,
+    |b: &mut Bencher, count: usize, needles: Vec<&str>| {
+        let haystack = HAYSTACK_SHERLOCK;
+
+        b.bytes = haystack.len() as u64;
+        let mut nfa = NFA::from_dictionary(needles);
+        nfa.ignore_prefixes();
+        let safe_ddfa = nfa
+            .powerset_construction()
+            .into_dfa()
+            .unwrap()
+            .into_safe_ddfa();
+
+        b.iter(|| assert_eq!(count, safe_ddfa.find(haystack.as_bytes()).count()));
+    }
+);
+
+sherlock_benches!(
+    safe_ddfa_boxed,
+    |b: &mut Bencher, count: usize, needles: Vec<&str>| {
+        let haystack = HAYSTACK_SHERLOCK;
+
+        b.bytes = haystack.len() as u64;
+        let mut nfa = NFA::from_dictionary(needles);
+        nfa.ignore_prefixes();
+        let safe_ddfa: &SafeDDFA = &nfa
+            .powerset_construction()
+            .into_dfa()
+            .unwrap()
+            .into_safe_ddfa();
+
+        b.iter(|| assert_eq!(count, Automaton::find(safe_ddfa, haystack.as_bytes()).count()));
+    }
+);
+
+// Times the powerset construction itself rather than the search. Run with and without
+//  `--features fast-hash` to compare the hash maps of the construction.
+sherlock_benches!(
+    powerset_construction,
+    |b: &mut Bencher, count: usize, needles: Vec<&str>| {
+        let haystack = HAYSTACK_SHERLOCK;
+
+        let mut nfa = NFA::from_dictionary(needles);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        assert_eq!(count, dnfa.find(haystack.as_bytes()).count());
+
+        b.iter(|| nfa.powerset_construction());
+    }
+);
@@ -11,6 +11,7 @@ fn main() {
     let options = DotOptions {
         bold_dict_edges: true,
         suppress_stuck_state: true,
+        ..DotOptions::default()
     };
     println!("{}", nfa.dot(options).trim());
 }
@@ -0,0 +1,17 @@
+use std::env;
+
+use dnfa::nfa::*;
+
+fn main() {
+    let dict: Vec<String> = env::args().skip(1).collect();
+    let mut nfa = NFA::from_dictionary(dict);
+    nfa.ignore_prefixes();
+    let dfa = nfa.into_minimal_dfa().unwrap();
+    let options = DotOptions {
+        bold_dict_edges: true,
+        suppress_stuck_state: true,
+        show_pattern_ends: true,
+        ..DotOptions::default()
+    };
+    println!("{}", dfa.dot(options).trim());
+}
@@ -1,7 +1,14 @@
 use std::fmt::Debug;
+use std::marker::PhantomData;
+
+#[cfg(feature = "parallel")]
+use crate::nfa::Input as Byte;
+use crate::nfa::PatternNumber;
 
 pub trait Automaton<Input: Ord> {
     type State: Debug;
+    /// What a match reports about the pattern that matched, e.g. its number in the dictionary
+    type Payload: Copy + Debug;
 
     fn start_state(&self) -> Self::State;
     fn stuck_state(&self) -> Self::State;
@@ -10,8 +17,33 @@ pub trait Automaton<Input: Ord> {
 
     fn has_match(&self, si: &Self::State, patt_no_offset: usize) -> bool;
 
-    fn get_match(&self, si: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match;
+    fn get_match(
+        &self,
+        si: &Self::State,
+        patt_no_offset: usize,
+        text_offset: usize,
+    ) -> Match<Self::Payload>;
+
+    /// Whether any pattern ends in this state
+    fn state_is_final(&self, state: &Self::State) -> bool {
+        self.has_match(state, 0)
+    }
+
+    /// Whether no more matches can be found from this state, whatever the rest of the input is.
+    ///  The default never gives up, which is always safe.
+    fn state_is_stuck(&self, _state: &Self::State) -> bool {
+        false
+    }
+
+    /// The length of the longest match, if the automaton knows it. The searches that run the
+    ///  automaton from every start position use it to stop early when the automaton doesn't get
+    ///  stuck. The default doesn't know, which is always safe.
+    fn max_match_len(&self) -> Option<usize> {
+        None
+    }
 
+    /// Finds the matches in `s`, with every pattern that ends at a position. The state isn't
+    ///  reset after a match, so matches can overlap.
     fn find<'i, 'a>(&'a self, s: &'i [Input]) -> Matches<'i, 'a, Input, Self>
     where
         Self: Sized,
@@ -21,6 +53,111 @@ pub trait Automaton<Input: Ord> {
             input: s,
             offset: 0,
             state: Self::start_state(self),
+            pending_patt_offset: 0,
+        }
+    }
+
+    /// Like `find`, but starts the search at byte `start` of `s`. The match offsets are still
+    ///  relative to the start of `s`, unlike with `find(&s[start..])`.
+    fn find_at<'i, 'a>(&'a self, s: &'i [Input], start: usize) -> Matches<'i, 'a, Input, Self>
+    where
+        Self: Sized,
+    {
+        Matches {
+            aut: self,
+            input: s,
+            offset: start,
+            state: Self::start_state(self),
+            pending_patt_offset: 0,
+        }
+    }
+
+    /// Same as `find_at`
+    fn find_iter_from<'i, 'a>(
+        &'a self,
+        s: &'i [Input],
+        start: usize,
+    ) -> Matches<'i, 'a, Input, Self>
+    where
+        Self: Sized,
+    {
+        self.find_at(s, start)
+    }
+
+    /// Finds non-overlapping matches, choosing the leftmost start and then the longest match at
+    ///  that start. Since the automaton can't tell whether a longer match is still coming, the
+    ///  whole haystack is scanned (and all matches buffered) before the first match is returned.
+    fn find_leftmost_longest<'i, 'a>(
+        &'a self,
+        haystack: &'i [Input],
+    ) -> LeftmostLongest<'i, 'a, Input, Self>
+    where
+        Self: Sized,
+    {
+        LeftmostLongest {
+            aut: self,
+            input: haystack,
+            scanned: false,
+            matches: Vec::new(),
+            last_end: 0,
+        }
+    }
+
+    /// Finds all matches, including overlapping ones, ordered by start and then by end. The
+    ///  automaton is started at every position in turn: after the matches that start at a
+    ///  position, the state is reset to the start state and the scan continues at the position
+    ///  after that start. So an anchored automaton (without `ignore_prefixes` or failure links)
+    ///  also finds the matches that don't start at the beginning of `s`, unlike with `find`.
+    /// The scan from a position stops when the automaton gets stuck or has gone further than
+    ///  `max_match_len`, and otherwise runs to the end of `s`.
+    fn find_overlapping<'i, 'a>(&'a self, s: &'i [Input]) -> OverlappingMatches<'i, 'a, Input, Self>
+    where
+        Self: Sized,
+    {
+        OverlappingMatches {
+            aut: self,
+            input: s,
+            start: 0,
+            offset: 0,
+            state: Self::start_state(self),
+            patt_no_offset: 0,
+            max_len: self.max_match_len(),
+        }
+    }
+
+    /// Finds non-overlapping leftmost-longest matches without buffering the whole haystack.
+    ///  From each position it runs the automaton, keeping the match that starts first and then
+    ///  ends last, and continues after that match. If there was no match, it tries again from
+    ///  the next position.
+    /// A scan stops when the automaton is stuck, or when it's `max_match_len` past the start of
+    ///  the best match so far (or past the position it started from), since no better match can
+    ///  end after that. So this takes linear time for automata that never get stuck too, like
+    ///  those after `ignore_prefixes`, as long as they know `max_match_len`.
+    fn find_leftmost<'i, 'a>(&'a self, s: &'i [Input]) -> LeftmostMatches<'i, 'a, Input, Self>
+    where
+        Self: Sized,
+    {
+        LeftmostMatches {
+            aut: self,
+            input: s,
+            offset: 0,
+            max_len: self.max_match_len(),
+        }
+    }
+
+    /// Like `find`, but takes the input one item at a time from an iterator, without buffering
+    ///  it. Therefore the matches only record where they end.
+    fn find_from_iter<I>(&self, iter: I) -> IterMatches<'_, Input, I, Self>
+    where
+        Self: Sized,
+        I: Iterator<Item = Input>,
+    {
+        IterMatches {
+            aut: self,
+            input: iter,
+            offset: 0,
+            state: Self::start_state(self),
+            pending_patt_offset: 0,
         }
     }
 }
@@ -28,12 +165,12 @@ pub trait Automaton<Input: Ord> {
 // This is from burntsushi/aho-corasick.
 /// Records a match in the search text.
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-pub struct Match {
-    /// The pattern index.
+pub struct Match<P = PatternNumber> {
+    /// The payload of the matched pattern. By default the pattern index.
     ///
     /// This corresponds to the ordering in which the matched pattern was
     /// added to the automaton, starting at `0`.
-    pub patt_no: usize,
+    pub payload: P,
     /// The starting byte offset of the match in the search text.
     pub start: usize,
     /// The ending byte offset of the match in the search text.
@@ -43,7 +180,16 @@ pub struct Match {
     pub end: usize,
 }
 
-/// An iterator of non-overlapping matches for in-memory text.
+/// Records the end of a match in a search text that isn't kept in memory.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct EndMatch<P = PatternNumber> {
+    /// The payload of the matched pattern. By default the pattern index.
+    pub payload: P,
+    /// The ending byte offset of the match in the search text.
+    pub end: usize,
+}
+
+/// An iterator of the matches for in-memory text, with every pattern that ends at a position.
 ///
 /// This iterator yields `Match` values.
 #[derive(Debug)]
@@ -52,21 +198,319 @@ pub struct Matches<'i, 'a, Input: 'i + Ord, A: 'a + Automaton<Input>> {
     input: &'i [Input],
     offset: usize,
     state: A::State,
+    // The next pattern to report that ends at `offset`, or 0 to move on to the next position
+    pending_patt_offset: usize,
+}
+
+impl<'i, 'a, Input: Ord, A: Automaton<Input>> Matches<'i, 'a, Input, A> {
+    /// Restarts the search from the beginning of the text.
+    pub fn reset(&mut self) {
+        self.offset = 0;
+        self.state = self.aut.start_state();
+        self.pending_patt_offset = 0;
+    }
 }
 
 impl<'i, 'a, Input: Ord, A: Automaton<Input>> Iterator for Matches<'i, 'a, Input, A> {
-    type Item = Match;
+    type Item = Match<A::Payload>;
 
-    fn next(&mut self) -> Option<Match> {
-        let mut offset = self.offset;
-        while offset < self.input.len() {
-            self.state = self.aut.next_state(&self.state, &self.input[offset]);
-            offset += 1;
+    fn next(&mut self) -> Option<Match<A::Payload>> {
+        if self.pending_patt_offset > 0 {
+            if self.aut.has_match(&self.state, self.pending_patt_offset) {
+                let m = self
+                    .aut
+                    .get_match(&self.state, self.pending_patt_offset, self.offset);
+                self.pending_patt_offset += 1;
+                return Some(m);
+            }
+            self.pending_patt_offset = 0;
+        }
+        while self.offset < self.input.len() {
+            self.state = self.aut.next_state(&self.state, &self.input[self.offset]);
+            self.offset += 1;
             if self.aut.has_match(&self.state, 0) {
-                self.offset = offset;
-                return Some(self.aut.get_match(&self.state, 0, offset));
+                self.pending_patt_offset = 1;
+                return Some(self.aut.get_match(&self.state, 0, self.offset));
             }
         }
         None
     }
 }
+
+/// An iterator of all matches for in-memory text, including overlapping ones.
+///
+/// This iterator yields `Match` values.
+#[derive(Debug)]
+pub struct OverlappingMatches<'i, 'a, Input: 'i + Ord, A: 'a + Automaton<Input>> {
+    aut: &'a A,
+    input: &'i [Input],
+    // Where the automaton was last started, and so where the reported matches start
+    start: usize,
+    offset: usize,
+    state: A::State,
+    // The next pattern to report that ends at `offset`
+    patt_no_offset: usize,
+    max_len: Option<usize>,
+}
+
+impl<'i, 'a, Input: Ord, A: Automaton<Input>> Iterator for OverlappingMatches<'i, 'a, Input, A> {
+    type Item = Match<A::Payload>;
+
+    fn next(&mut self) -> Option<Match<A::Payload>> {
+        loop {
+            while self.offset > self.start && self.aut.has_match(&self.state, self.patt_no_offset) {
+                let m = self
+                    .aut
+                    .get_match(&self.state, self.patt_no_offset, self.offset);
+                self.patt_no_offset += 1;
+                // Matches that start later are found again from their own start
+                if m.start == self.start {
+                    return Some(m);
+                }
+            }
+            self.patt_no_offset = 0;
+            let done = self.offset >= self.input.len()
+                || self.aut.state_is_stuck(&self.state)
+                || self
+                    .max_len
+                    .is_some_and(|max_len| self.offset - self.start >= max_len);
+            if done {
+                self.start += 1;
+                if self.start >= self.input.len() {
+                    self.offset = self.start;
+                    return None;
+                }
+                self.offset = self.start;
+                self.state = self.aut.start_state();
+            } else {
+                self.state = self.aut.next_state(&self.state, &self.input[self.offset]);
+                self.offset += 1;
+            }
+        }
+    }
+}
+
+/// An iterator of non-overlapping leftmost-longest matches for in-memory text.
+///
+/// This iterator yields `Match` values.
+#[derive(Debug)]
+pub struct LeftmostLongest<'i, 'a, Input: 'i + Ord, A: 'a + Automaton<Input>> {
+    aut: &'a A,
+    input: &'i [Input],
+    scanned: bool,
+    // All matches, by start and then longest first, in reverse so the next one can be popped
+    matches: Vec<Match<A::Payload>>,
+    last_end: usize,
+}
+
+impl<'i, 'a, Input: Ord, A: Automaton<Input>> Iterator for LeftmostLongest<'i, 'a, Input, A> {
+    type Item = Match<A::Payload>;
+
+    fn next(&mut self) -> Option<Match<A::Payload>> {
+        if !self.scanned {
+            self.scanned = true;
+            let mut state = self.aut.start_state();
+            for (i, input) in self.input.iter().enumerate() {
+                state = self.aut.next_state(&state, input);
+                let mut patt_no_offset = 0;
+                while self.aut.has_match(&state, patt_no_offset) {
+                    self.matches
+                        .push(self.aut.get_match(&state, patt_no_offset, i + 1));
+                    patt_no_offset += 1;
+                }
+            }
+            self.matches
+                .sort_by(|m1, m2| (m2.start, m1.end).cmp(&(m1.start, m2.end)));
+        }
+        while let Some(m) = self.matches.pop() {
+            if m.start >= self.last_end {
+                self.last_end = m.end;
+                return Some(m);
+            }
+        }
+        None
+    }
+}
+
+/// An iterator of non-overlapping leftmost-longest matches for in-memory text, that only
+///  scans ahead as far as a better match could still end.
+///
+/// This iterator yields `Match` values.
+#[derive(Debug)]
+pub struct LeftmostMatches<'i, 'a, Input: 'i + Ord, A: 'a + Automaton<Input>> {
+    aut: &'a A,
+    input: &'i [Input],
+    offset: usize,
+    max_len: Option<usize>,
+}
+
+impl<'i, 'a, Input: Ord, A: Automaton<Input>> Iterator for LeftmostMatches<'i, 'a, Input, A> {
+    type Item = Match<A::Payload>;
+
+    fn next(&mut self) -> Option<Match<A::Payload>> {
+        while self.offset < self.input.len() {
+            let mut best: Option<Match<A::Payload>> = None;
+            let mut state = self.aut.start_state();
+            for (i, input) in self.input[self.offset..].iter().enumerate() {
+                if let Some(max_len) = self.max_len {
+                    let from = best.map_or(self.offset, |best| best.start);
+                    if self.offset + i >= from + max_len {
+                        break;
+                    }
+                }
+                state = self.aut.next_state(&state, input);
+                let mut patt_no_offset = 0;
+                while self.aut.has_match(&state, patt_no_offset) {
+                    let m = self
+                        .aut
+                        .get_match(&state, patt_no_offset, self.offset + i + 1);
+                    if best.is_none_or(|best| (m.start, best.end) < (best.start, m.end)) {
+                        best = Some(m);
+                    }
+                    patt_no_offset += 1;
+                }
+                if self.aut.state_is_stuck(&state) {
+                    break;
+                }
+            }
+            match best {
+                Some(m) => {
+                    self.offset = m.end;
+                    return Some(m);
+                }
+                None => self.offset += 1,
+            }
+        }
+        None
+    }
+}
+
+/// An iterator of the matches for text that's read from an iterator, with every pattern that
+///  ends at a position, like `Matches`.
+///
+/// This iterator yields `EndMatch` values.
+#[derive(Debug)]
+pub struct IterMatches<'a, Input: Ord, I: Iterator<Item = Input>, A: 'a + Automaton<Input>> {
+    aut: &'a A,
+    input: I,
+    offset: usize,
+    state: A::State,
+    // The next pattern to report that ends at `offset`, or 0 to move on to the next position
+    pending_patt_offset: usize,
+}
+
+impl<'a, Input: Ord, I: Iterator<Item = Input>, A: Automaton<Input>> Iterator
+    for IterMatches<'a, Input, I, A>
+{
+    type Item = EndMatch<A::Payload>;
+
+    fn next(&mut self) -> Option<EndMatch<A::Payload>> {
+        if self.pending_patt_offset > 0 {
+            if self.aut.has_match(&self.state, self.pending_patt_offset) {
+                let m = self
+                    .aut
+                    .get_match(&self.state, self.pending_patt_offset, self.offset);
+                self.pending_patt_offset += 1;
+                return Some(EndMatch {
+                    payload: m.payload,
+                    end: m.end,
+                });
+            }
+            self.pending_patt_offset = 0;
+        }
+        for input in &mut self.input {
+            self.state = self.aut.next_state(&self.state, &input);
+            self.offset += 1;
+            if self.aut.has_match(&self.state, 0) {
+                let m = self.aut.get_match(&self.state, 0, self.offset);
+                self.pending_patt_offset = 1;
+                return Some(EndMatch {
+                    payload: m.payload,
+                    end: m.end,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// A search over a stream of chunks, e.g. read from an `io::Read`. The state of the automaton
+///  is kept between chunks, so matches that span a chunk boundary are found, and the offsets
+///  of the matches are counted from the start of the stream.
+#[derive(Debug)]
+pub struct Searcher<'a, Input: Ord, A: 'a + Automaton<Input>> {
+    aut: &'a A,
+    state: A::State,
+    byte_offset: usize,
+    input: PhantomData<Input>,
+}
+
+impl<'a, Input: Ord, A: Automaton<Input>> Searcher<'a, Input, A> {
+    pub fn new(aut: &'a A) -> Self {
+        Searcher {
+            aut,
+            state: aut.start_state(),
+            byte_offset: 0,
+            input: PhantomData,
+        }
+    }
+
+    /// Runs the automaton over the next chunk of the stream, and returns the matches that end
+    ///  in it. Like `find`, this reports every pattern that ends at a position.
+    pub fn feed(&mut self, chunk: &[Input]) -> Vec<Match<A::Payload>> {
+        let mut matches = Vec::new();
+        for input in chunk {
+            self.state = self.aut.next_state(&self.state, input);
+            self.byte_offset += 1;
+            let mut patt_no_offset = 0;
+            while self.aut.has_match(&self.state, patt_no_offset) {
+                matches.push(
+                    self.aut
+                        .get_match(&self.state, patt_no_offset, self.byte_offset),
+                );
+                patt_no_offset += 1;
+            }
+        }
+        matches
+    }
+}
+
+impl<'a, Input: Ord, A: Automaton<Input>> From<&'a A> for Searcher<'a, Input, A> {
+    fn from(aut: &'a A) -> Self {
+        Searcher::new(aut)
+    }
+}
+
+/// Runs `find` on every haystack, in parallel with rayon, and gives the matches per haystack in
+///  the same order as the haystacks. The automaton is shared between the threads, so it has to
+///  be `Sync`. `NFA`, `DFA` and `SafeDDFA` are; the `DDFA` isn't, because of its raw pointers,
+///  so use a `SafeDDFA` instead.
+#[cfg(feature = "parallel")]
+pub fn search_parallel<A, H>(aut: &A, haystacks: &[H]) -> Vec<Vec<Match<A::Payload>>>
+where
+    A: Automaton<Byte> + Sync + Send,
+    A::Payload: Send,
+    H: AsRef<[Byte]> + Sync,
+{
+    use rayon::prelude::*;
+
+    haystacks
+        .par_iter()
+        .map(|haystack| aut.find(haystack.as_ref()).collect())
+        .collect()
+}
+
+/// The total number of matches of `search_parallel`, without keeping the matches around
+#[cfg(feature = "parallel")]
+pub fn search_parallel_count<A, H>(aut: &A, haystacks: &[H]) -> usize
+where
+    A: Automaton<Byte> + Sync + Send,
+    H: AsRef<[Byte]> + Sync,
+{
+    use rayon::prelude::*;
+
+    haystacks
+        .par_iter()
+        .map(|haystack| aut.find(haystack.as_ref()).count())
+        .sum()
+}
@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::automaton::{Automaton, Match};
+use crate::nfa::{DotOptions, PatternNumber, StateNumber, START, STUCK};
+use crate::util::{dot_header, dot_pattern_ends};
+
+pub type Input = char;
+
+/// A match in a text of `char`s. The offsets count `char`s, not bytes.
+pub type CharMatch<P = PatternNumber> = Match<P>;
+
+#[derive(Clone, Debug, Default)]
+struct CharNFAState {
+    transitions: HashMap<Input, BTreeSet<StateNumber>>,
+    // Where any char without an entry in `transitions` goes. There are too many chars to give
+    //  each of them a transition, as the byte-based NFA does for `ignore_prefixes`.
+    default_transition: BTreeSet<StateNumber>,
+    pattern_ends: Vec<PatternNumber>,
+}
+
+impl CharNFAState {
+    fn next_states(&self, input: Input) -> &BTreeSet<StateNumber> {
+        self.transitions
+            .get(&input)
+            .unwrap_or(&self.default_transition)
+    }
+
+    fn is_final(&self) -> bool {
+        !self.pattern_ends.is_empty()
+    }
+}
+
+/// An `NFA` over `char`s instead of bytes, so every character of a pattern is a single
+///  transition, whatever the length of its UTF-8 encoding.
+#[derive(Debug, Default)]
+pub struct CharNFA {
+    states: Vec<CharNFAState>,
+    dict: Vec<Vec<Input>>,
+}
+
+impl CharNFA {
+    /// Builds a trie of the patterns, where pattern `i` ends in a state with `i` in its pattern
+    ///  ends, like `NFA::from_dictionary`
+    pub fn from_dictionary<P, I>(dict: I) -> Self
+    where
+        P: AsRef<str>,
+        I: IntoIterator<Item = P>,
+    {
+        let mut nfa = CharNFA {
+            states: vec![CharNFAState::default(), CharNFAState::default()],
+            dict: dict
+                .into_iter()
+                .map(|p| p.as_ref().chars().collect())
+                .collect(),
+        };
+        for (patt_no, chars) in nfa.dict.iter().enumerate() {
+            let mut cur_state = START;
+            for &c in chars {
+                let existing = nfa.states[cur_state]
+                    .transitions
+                    .get(&c)
+                    .and_then(|to| to.iter().next().cloned());
+                cur_state = match existing {
+                    Some(nxt_state) => nxt_state,
+                    None => {
+                        let nxt_state = nfa.states.len();
+                        nfa.states.push(CharNFAState::default());
+                        nfa.states[cur_state]
+                            .transitions
+                            .entry(c)
+                            .or_default()
+                            .insert(nxt_state);
+                        nxt_state
+                    }
+                };
+            }
+            nfa.states[cur_state].pattern_ends.push(patt_no);
+        }
+        nfa
+    }
+
+    /// The number of states, including the stuck state
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Lets matches start anywhere in the input, with a self-loop on every char on the start
+    ///  state
+    pub fn ignore_prefixes(&mut self) {
+        self.add_self_loops(START);
+    }
+
+    /// Lets matches end anywhere in the input, with a self-loop on every char on the final
+    ///  states
+    pub fn ignore_postfixes(&mut self) {
+        for state in 0..self.states.len() {
+            if self.states[state].is_final() {
+                self.add_self_loops(state);
+            }
+        }
+    }
+
+    fn add_self_loops(&mut self, state: StateNumber) {
+        let st = &mut self.states[state];
+        for to in st.transitions.values_mut() {
+            to.insert(state);
+        }
+        st.default_transition.insert(state);
+    }
+
+    /// The patterns that are accepted after the whole `input`
+    pub fn apply(&self, input: &[Input]) -> Vec<PatternNumber> {
+        let mut cur_states = self.start_state();
+        for &c in input {
+            cur_states = self.next_state(&cur_states, &c);
+        }
+        cur_states
+            .iter()
+            .flat_map(|&state| self.states[state].pattern_ends.iter().cloned())
+            .collect()
+    }
+
+    /// The deterministic `CharNFA` for this one, like `NFA::powerset_construction`. Besides the
+    ///  chars that have a transition somewhere, each set of states also gets a target for all
+    ///  other chars.
+    pub fn powerset_construction(&self) -> Self {
+        let mut dnfa = CharNFA {
+            states: vec![CharNFAState::default(), CharNFAState::default()],
+            dict: self.dict.clone(),
+        };
+        let mut states_map: HashMap<BTreeSet<StateNumber>, StateNumber> = HashMap::new();
+        states_map.insert(BTreeSet::new(), STUCK);
+        states_map.insert(self.stuck_state(), STUCK);
+        states_map.insert(self.start_state(), START);
+        dnfa.states[START].pattern_ends = self.states[START].pattern_ends.clone();
+
+        let mut worklist = vec![(self.start_state(), START)];
+        while let Some((cur_states, cur_num)) = worklist.pop() {
+            let chars: BTreeSet<Input> = cur_states
+                .iter()
+                .flat_map(|&state| self.states[state].transitions.keys().cloned())
+                .collect();
+            let targets = chars
+                .into_iter()
+                .map(|c| (Some(c), self.next_state(&cur_states, &c)))
+                .chain(Some((
+                    None,
+                    cur_states
+                        .iter()
+                        .flat_map(|&state| self.states[state].default_transition.iter().cloned())
+                        .collect(),
+                )));
+            for (c, nxt_states) in targets {
+                let nxt_num = match states_map.get(&nxt_states) {
+                    Some(&nxt_num) => nxt_num,
+                    None => {
+                        let nxt_num = dnfa.states.len();
+                        let pattern_ends: BTreeSet<PatternNumber> = nxt_states
+                            .iter()
+                            .flat_map(|&state| self.states[state].pattern_ends.iter().cloned())
+                            .collect();
+                        dnfa.states.push(CharNFAState {
+                            pattern_ends: pattern_ends.into_iter().collect(),
+                            ..CharNFAState::default()
+                        });
+                        states_map.insert(nxt_states.clone(), nxt_num);
+                        worklist.push((nxt_states, nxt_num));
+                        nxt_num
+                    }
+                };
+                if nxt_num == STUCK {
+                    continue;
+                }
+                let cur_state = &mut dnfa.states[cur_num];
+                match c {
+                    Some(c) => cur_state.transitions.entry(c).or_default().insert(nxt_num),
+                    None => cur_state.default_transition.insert(nxt_num),
+                };
+            }
+        }
+        dnfa
+    }
+
+    /// The DOT graph of this automaton, like `NFA::dot`. The transitions for all chars without a
+    ///  transition of their own are labeled "other".
+    pub fn dot(&self, options: DotOptions) -> String {
+        use std::fmt::Write;
+        let mut out = String::new();
+        macro_rules! w {
+            ($($tt:tt)*) => { {write!(out, $($tt)*)}.unwrap() }
+        }
+
+        let dict: Vec<Vec<u8>> = self
+            .dict
+            .iter()
+            .map(|chars| chars.iter().collect::<String>().into_bytes())
+            .collect();
+        dot_header(&mut out, &dict, &options.label);
+
+        let mut original_edges = BTreeSet::new();
+        for chars in &self.dict {
+            let mut cur_state = START;
+            for c in chars {
+                if let Some(&nxt_state) = self.states[cur_state]
+                    .transitions
+                    .get(c)
+                    .and_then(|to| to.iter().next())
+                {
+                    original_edges.insert((cur_state, nxt_state));
+                    cur_state = nxt_state;
+                }
+            }
+        }
+
+        for (from, state) in self.states.iter().enumerate() {
+            if options.suppress_stuck_state && from == STUCK {
+                continue;
+            }
+            w!("    {}", from);
+            if from == STUCK {
+                w!(r#" [label="⊥"]"#);
+            }
+            if state.is_final() {
+                w!(" [peripheries=2]");
+                if options.show_pattern_ends {
+                    w!("{}", dot_pattern_ends(&state.pattern_ends));
+                }
+            }
+            w!(";\n");
+            let mut targets: BTreeMap<StateNumber, Vec<String>> = BTreeMap::new();
+            let mut chars: Vec<&Input> = state.transitions.keys().collect();
+            chars.sort();
+            for c in chars {
+                for &to in &state.transitions[c] {
+                    let label = format!("{:?}", c)
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"");
+                    targets.entry(to).or_default().push(label);
+                }
+            }
+            for &to in &state.default_transition {
+                targets.entry(to).or_default().push("other".to_string());
+            }
+            for (to, labels) in targets {
+                if options.suppress_stuck_state && to == STUCK {
+                    continue;
+                }
+                w!("    {} -> {}", from, to);
+                if options.bold_dict_edges && original_edges.contains(&(from, to)) {
+                    w!(" [style=bold]");
+                }
+                w!(" [label=\"{}\"];\n", labels.join(", "));
+            }
+        }
+
+        w!("}}");
+        out
+    }
+}
+
+impl Automaton<Input> for CharNFA {
+    type State = BTreeSet<StateNumber>;
+    type Payload = PatternNumber;
+
+    fn start_state(&self) -> Self::State {
+        [START].iter().cloned().collect()
+    }
+
+    fn stuck_state(&self) -> Self::State {
+        [STUCK].iter().cloned().collect()
+    }
+
+    #[inline]
+    fn state_is_stuck(&self, states: &Self::State) -> bool {
+        states.iter().all(|&state| state == STUCK)
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        Some(self.dict.iter().map(|chars| chars.len()).max().unwrap_or(0))
+    }
+
+    #[inline]
+    fn next_state(&self, states: &Self::State, &input: &Input) -> Self::State {
+        states
+            .iter()
+            .flat_map(|&state| self.states[state].next_states(input).iter().cloned())
+            .collect()
+    }
+
+    #[inline]
+    fn has_match(&self, states: &Self::State, patt_no_offset: usize) -> bool {
+        let matches: usize = states
+            .iter()
+            .map(|&state| self.states[state].pattern_ends.len())
+            .sum();
+        patt_no_offset < matches
+    }
+
+    #[inline]
+    fn get_match(&self, states: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match {
+        let patt_no = states
+            .iter()
+            .flat_map(|&state| self.states[state].pattern_ends.iter().cloned())
+            .nth(patt_no_offset)
+            .expect("There is no match of this pattern!");
+        Match {
+            payload: patt_no,
+            start: text_offset - self.dict[patt_no].len(),
+            end: text_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn from_dictionary() {
+        let nfa = CharNFA::from_dictionary(["日本語", "語学"]);
+        // stuck, start, 日, 日本, 日本語, 語, 語学: one state per char, not per byte
+        assert_eq!(nfa.state_count(), 7);
+        assert_eq!(nfa.apply(&chars("日本語")), vec![0]);
+        assert_eq!(nfa.apply(&chars("語学")), vec![1]);
+        assert!(nfa.apply(&chars("日本語学")).is_empty());
+        assert!(nfa.apply(&chars("日本")).is_empty());
+
+        let mut nfa = nfa;
+        nfa.ignore_postfixes();
+        assert_eq!(nfa.apply(&chars("日本語学")), vec![0]);
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.apply(&chars("日本語学")), vec![0, 1]);
+    }
+
+    #[test]
+    fn find() {
+        let mut nfa = CharNFA::from_dictionary(["日本語", "語学", "本"]);
+        nfa.ignore_prefixes();
+        let text = chars("私は日本語学校の学生です");
+        let found: Vec<CharMatch> = nfa.find(&text).collect();
+        let spans: Vec<_> = found.iter().map(|m| (m.payload, m.start, m.end)).collect();
+        assert_eq!(spans, vec![(2, 3, 4), (0, 2, 5), (1, 4, 6)]);
+
+        let dnfa = nfa.powerset_construction();
+        assert!(dnfa.find(&text).eq(found.into_iter()));
+        assert!(dnfa
+            .states
+            .iter()
+            .all(|st| st.transitions.values().all(|to| to.len() == 1)
+                && st.default_transition.len() <= 1));
+        assert_eq!(dnfa.apply(&chars("x日本語")), vec![0]);
+    }
+
+    #[test]
+    fn dot() {
+        let mut nfa = CharNFA::from_dictionary(["ab\"", "é"]);
+        nfa.ignore_prefixes();
+        let dot = nfa.dot(DotOptions {
+            suppress_stuck_state: true,
+            ..DotOptions::default()
+        });
+        assert!(dot.contains("[label=\"'\\\"'\"]"));
+        assert!(dot.contains("    1 -> 1 [label=\"'a', 'é', other\"];"));
+        assert!(!dot.contains("⊥"));
+    }
+}
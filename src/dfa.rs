@@ -1,28 +1,116 @@
 use bit_vec::BitVec;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::fmt;
 use std::mem;
 
 use crate::automaton::{Automaton, Match};
-use crate::nfa::{START, STUCK};
+use crate::nfa::{pattern_len, DotOptions, START, STUCK};
+use crate::scc::tarjan_sccs;
+use crate::util::{dot_header, dot_pattern_ends, implode_ranges};
 
 pub type Input = u8;
 pub type StateNumber = usize;
 pub type PatternNumber = usize;
 
+#[derive(PartialEq, Eq)]
 pub struct DFAState {
-    transitions: Box<[StateNumber]>,
+    transitions: [StateNumber; 256],
     pattern_ends: Vec<PatternNumber>,
 }
 
+#[derive(PartialEq, Eq)]
 pub struct DFA {
     states: Box<[DFAState]>,
     finals: BitVec,
     dict: Vec<Vec<Input>>,
+    dict_index: Option<HashMap<PatternNumber, usize>>,
 }
 
+/// Errors from `DFA::from_bytes`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The bytes don't start with `DNFA`
+    BadMagic,
+    /// The format version is not one this version of the crate can read
+    UnsupportedVersion,
+    /// The bytes end before the DFA does
+    Truncated,
+    /// A transition goes to a state that doesn't exist
+    InvalidStateIndex,
+    /// A state ends a pattern that isn't in the dictionary
+    InvalidPatternNumber,
+    /// There are fewer states than the stuck and start states
+    MissingStates,
+    /// There are more bytes after the DFA
+    TrailingBytes,
+}
+
+/// Errors from `DfaBuilder`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// State `from` already has a transition on `on` to another state
+    DuplicateTransition { from: StateNumber, on: Input },
+    /// A state ends a pattern that isn't in the dictionary
+    InvalidPatternNumber,
+}
+
+/// Builds a `DFA` state by state, for an automaton that is already known to be deterministic.
+///  Missing transitions go to the stuck state. See `NDFA` for building one from an NFA.
+pub struct DfaBuilder {
+    transitions: Vec<[StateNumber; 256]>,
+    pattern_ends: Vec<Vec<PatternNumber>>,
+    finals: BitVec,
+    dict: Vec<Vec<Input>>,
+    dict_index: Option<HashMap<PatternNumber, usize>>,
+}
+
+const MAGIC: &[u8; 4] = b"DNFA";
+const FORMAT_VERSION: u8 = 1;
+
 pub struct DDFA {
     states: Box<[DDFAState]>,
     dict: Vec<Vec<Input>>,
+    dict_index: Option<HashMap<PatternNumber, usize>>,
+}
+
+// A cursor over the bytes that `DFA::from_bytes` reads
+struct Reader<'b> {
+    bytes: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, len: usize) -> Result<&'b [u8], DeserializeError> {
+        if self.bytes.len() < len {
+            return Err(DeserializeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<usize, DeserializeError> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(word) as usize)
+    }
+}
+
+/// The same as a `DDFA`, but with the transitions as state numbers instead of pointers. That's
+///  an extra indirection per byte, but no unsafe code, and it can be shared between threads.
+pub struct SafeDDFA {
+    states: Box<[SafeDDFAState]>,
+    dict: Vec<Vec<Input>>,
+    dict_index: Option<HashMap<PatternNumber, usize>>,
+}
+
+struct SafeDDFAState {
+    transitions: Box<[u32]>,
+    pattern_ends: Vec<PatternNumber>,
+    is_final: bool,
 }
 
 // Living dangerously: raw pointers baby
@@ -34,12 +122,16 @@ pub struct DDFAState {
 }
 
 impl DFAState {
-    pub fn new(transitions: Box<[StateNumber]>, pattern_ends: Vec<PatternNumber>) -> Self {
+    pub fn new(transitions: [StateNumber; 256], pattern_ends: Vec<PatternNumber>) -> Self {
         DFAState {
             transitions,
             pattern_ends,
         }
     }
+
+    pub(crate) fn into_parts(self) -> ([StateNumber; 256], Vec<PatternNumber>) {
+        (self.transitions, self.pattern_ends)
+    }
 }
 
 impl DFA {
@@ -48,9 +140,155 @@ impl DFA {
             states,
             finals,
             dict,
+            dict_index: None,
+        }
+    }
+
+    // For an NFA built with `from_dictionary_with_ids`
+    pub(crate) fn with_dict_index(
+        mut self,
+        dict_index: Option<HashMap<PatternNumber, usize>>,
+    ) -> Self {
+        self.dict_index = dict_index;
+        self
+    }
+
+    // For converting back to an `NFA`
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn into_parts(
+        self,
+    ) -> (
+        Box<[DFAState]>,
+        Vec<Vec<Input>>,
+        Option<HashMap<PatternNumber, usize>>,
+    ) {
+        (self.states, self.dict, self.dict_index)
+    }
+
+    /// Serializes the DFA, to be loaded again with `from_bytes`. All numbers are little-endian
+    ///  `u32`s. The format is:
+    /// - the magic bytes `DNFA` and a version byte
+    /// - the number of states N, and the N * 256 transitions
+    /// - the finals as a bitvector of N bits, rounded up to whole bytes
+    /// - per state the number of pattern ends, followed by the pattern numbers
+    /// - the number of dictionary entries, and per entry its length and bytes
+    /// - a byte that's 1 if the patterns have ids (see `NFA::from_dictionary_with_ids`), and if
+    ///   so the id of every dictionary entry
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn push_u32(out: &mut Vec<u8>, n: usize) {
+            let n = u32::try_from(n).expect("The DFA is too large to serialize");
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+
+        let mut out = Vec::with_capacity(9 + self.states.len() * 256 * 4);
+        out.extend_from_slice(MAGIC);
+        out.push(FORMAT_VERSION);
+        push_u32(&mut out, self.states.len());
+        for state in self.states.iter() {
+            for &to in state.transitions.iter() {
+                push_u32(&mut out, to);
+            }
+        }
+        out.extend(self.finals.to_bytes());
+        for state in self.states.iter() {
+            push_u32(&mut out, state.pattern_ends.len());
+            for &patt_no in &state.pattern_ends {
+                push_u32(&mut out, patt_no);
+            }
+        }
+        push_u32(&mut out, self.dict.len());
+        for bytes in &self.dict {
+            push_u32(&mut out, bytes.len());
+            out.extend_from_slice(bytes);
+        }
+        match &self.dict_index {
+            Some(dict_index) => {
+                out.push(1);
+                let mut ids = vec![0; self.dict.len()];
+                for (&id, &idx) in dict_index {
+                    ids[idx] = id;
+                }
+                for id in ids {
+                    push_u32(&mut out, id);
+                }
+            }
+            None => out.push(0),
+        }
+        out
+    }
+
+    /// Loads a DFA that was serialized with `to_bytes`
+    pub fn from_bytes(b: &[u8]) -> Result<DFA, DeserializeError> {
+        let mut reader = Reader { bytes: b };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DeserializeError::BadMagic);
+        }
+        if reader.take(1)?[0] != FORMAT_VERSION {
+            return Err(DeserializeError::UnsupportedVersion);
+        }
+
+        let states_len = reader.u32()?;
+        if states_len <= START {
+            return Err(DeserializeError::MissingStates);
+        }
+        // Check the length up front, so a corrupt count can't make us allocate a lot
+        if reader.bytes.len() / (256 * 4) < states_len {
+            return Err(DeserializeError::Truncated);
+        }
+        let mut states = Vec::with_capacity(states_len);
+        for _ in 0..states_len {
+            let mut transitions = [STUCK; 256];
+            for to in transitions.iter_mut() {
+                *to = reader.u32()?;
+                if *to >= states_len {
+                    return Err(DeserializeError::InvalidStateIndex);
+                }
+            }
+            states.push(DFAState::new(transitions, Vec::new()));
+        }
+        let mut finals = BitVec::from_bytes(reader.take(states_len.div_ceil(8))?);
+        finals.truncate(states_len);
+        for state in &mut states {
+            let len = reader.u32()?;
+            for _ in 0..len {
+                state.pattern_ends.push(reader.u32()?);
+            }
+        }
+
+        let dict_len = reader.u32()?;
+        let mut dict = Vec::new();
+        for _ in 0..dict_len {
+            let len = reader.u32()?;
+            dict.push(reader.take(len)?.to_vec());
+        }
+        let dict_index = match reader.take(1)?[0] {
+            0 => None,
+            _ => {
+                let mut dict_index = HashMap::new();
+                for idx in 0..dict_len {
+                    dict_index.insert(reader.u32()?, idx);
+                }
+                Some(dict_index)
+            }
+        };
+        if !reader.bytes.is_empty() {
+            return Err(DeserializeError::TrailingBytes);
+        }
+        let known_pattern = |patt_no: &PatternNumber| match &dict_index {
+            Some(dict_index) => dict_index.contains_key(patt_no),
+            None => *patt_no < dict_len,
+        };
+        if !states
+            .iter()
+            .all(|state| state.pattern_ends.iter().all(known_pattern))
+        {
+            return Err(DeserializeError::InvalidPatternNumber);
         }
+
+        Ok(DFA::new(states.into_boxed_slice(), finals, dict).with_dict_index(dict_index))
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn into_ddfa(self) -> Result<DDFA, ()> {
         let states_len = self.states.len();
         let mut states =
@@ -65,14 +303,37 @@ impl DFA {
                     return Err(());
                 }
                 unsafe {
-                    v.push(states_start.offset(offset as isize) as *const DDFAState);
+                    v.push(states_start.add(offset) as *const DDFAState);
                 }
             }
             states[i].transitions = v.into_boxed_slice();
             states[i].pattern_ends = self.states[i].pattern_ends.clone();
             states[i].is_final = self.finals[i];
         }
-        Ok(DDFA::new(states, self.dict))
+        Ok(DDFA::new(states, self.dict, self.dict_index))
+    }
+
+    /// Like `into_ddfa`, but for a `SafeDDFA`. A transition to a state that doesn't exist panics
+    ///  when it's taken, instead of being refused here.
+    pub fn into_safe_ddfa(self) -> SafeDDFA {
+        assert!(self.states.len() <= u32::MAX as usize);
+        let finals = self.finals;
+        let states = self
+            .states
+            .into_vec()
+            .into_iter()
+            .enumerate()
+            .map(|(i, st)| SafeDDFAState {
+                transitions: st.transitions.iter().map(|&to| to as u32).collect(),
+                pattern_ends: st.pattern_ends,
+                is_final: finals[i],
+            })
+            .collect();
+        SafeDDFA {
+            states,
+            dict: self.dict,
+            dict_index: self.dict_index,
+        }
     }
 
     pub fn apply(&self, input: &[u8]) -> Vec<PatternNumber> {
@@ -85,10 +346,744 @@ impl DFA {
         }
         self.states[cur_state].pattern_ends.clone()
     }
+
+    /// `apply` for every input in a batch
+    pub fn apply_multi(&self, inputs: &[&[Input]]) -> Vec<Vec<PatternNumber>> {
+        inputs.iter().map(|input| self.apply(input)).collect()
+    }
+
+    /// The number of states, including the stuck state
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// The number of transitions that don't go to the stuck state. Out of `256` per state, so
+    ///  this shows how dense the transition table is.
+    pub fn non_stuck_transition_count(&self) -> usize {
+        self.states
+            .iter()
+            .map(|state| state.transitions.iter().filter(|&&to| to != STUCK).count())
+            .sum()
+    }
+
+    /// A quick estimate of the memory used by this DFA: the transition tables, the finals and
+    ///  the bytes of the dictionary. See `memory_estimate_bytes` for one that also counts the
+    ///  pattern ends and the vectors everything is kept in.
+    pub fn memory_usage_bytes(&self) -> usize {
+        let dict_byte_count: usize = self.dict.iter().map(|bytes| bytes.len()).sum();
+        self.states.len() * 256 * mem::size_of::<StateNumber>()
+            + self.finals.len().div_ceil(8)
+            + dict_byte_count
+    }
+
+    /// An estimate of the memory used by this DFA: the transition tables, the pattern ends and
+    ///  the dictionary, including the vectors they are kept in.
+    pub fn memory_estimate_bytes(&self) -> usize {
+        let states: usize = self
+            .states
+            .iter()
+            .map(|state| {
+                mem::size_of::<DFAState>()
+                    + state.pattern_ends.capacity() * mem::size_of::<PatternNumber>()
+            })
+            .sum();
+        let dict: usize = self
+            .dict
+            .iter()
+            .map(|bytes| mem::size_of::<Vec<Input>>() + bytes.capacity())
+            .sum();
+        mem::size_of::<DFA>() + states + self.finals.capacity() / 8 + dict
+    }
+
+    /// The states that can be reached from the start state, in breadth-first order.
+    pub fn reachable_states(&self) -> Vec<StateNumber> {
+        let mut visited = BitVec::from_elem(self.states.len(), false);
+        visited.set(START, true);
+        let mut order = vec![START];
+        let mut next = 0;
+        while next < order.len() {
+            let state = order[next];
+            next += 1;
+            for &nxt_state in self.states[state].transitions.iter() {
+                if !visited[nxt_state] {
+                    visited.set(nxt_state, true);
+                    order.push(nxt_state);
+                }
+            }
+        }
+        order
+    }
+
+    /// The states that can't be reached from the start state. The stuck state is never included,
+    ///  since it's the target of every missing transition.
+    pub fn unreachable_states(&self) -> Vec<StateNumber> {
+        let mut reachable = BitVec::from_elem(self.states.len(), false);
+        for state in self.reachable_states() {
+            reachable.set(state, true);
+        }
+        (0..self.states.len())
+            .filter(|&state| state != STUCK && !reachable[state])
+            .collect()
+    }
+
+    /// The DOT graph of this DFA, with the transitions to each state grouped into byte ranges
+    pub fn dot(&self, options: DotOptions) -> String {
+        use std::fmt::Write;
+        let mut out = String::new();
+        macro_rules! w {
+            ($($tt:tt)*) => { {write!(out, $($tt)*)}.unwrap() }
+        }
+
+        dot_header(&mut out, &self.dict, &options.label);
+
+        let mut original_edges = BTreeSet::new();
+        for bytes in &self.dict {
+            let mut cur_state = START;
+            for &byte in bytes {
+                let nxt_state = self.states[cur_state].transitions[byte as usize];
+                original_edges.insert((cur_state, nxt_state));
+                cur_state = nxt_state;
+            }
+        }
+
+        for (from, state) in self.states.iter().enumerate() {
+            if options.suppress_stuck_state && from == STUCK {
+                continue;
+            }
+            w!("    {}", from);
+            if from == STUCK {
+                w!(r#" [label="⊥"]"#);
+            }
+            if self.finals[from] {
+                w!(" [peripheries=2]");
+                if options.show_pattern_ends {
+                    w!("{}", dot_pattern_ends(&state.pattern_ends));
+                }
+            }
+            w!(";\n");
+            let mut targets: BTreeMap<StateNumber, BTreeSet<Input>> = BTreeMap::new();
+            for (byte, &to) in state.transitions.iter().enumerate() {
+                targets.entry(to).or_default().insert(byte as Input);
+            }
+            for (to, bytes) in targets {
+                if options.suppress_stuck_state && to == STUCK {
+                    continue;
+                }
+                w!("    {} -> {}", from, to);
+                if options.bold_dict_edges && original_edges.contains(&(from, to)) {
+                    w!(" [style=bold]");
+                }
+                w!(" [label=\"{}\"];\n", implode_ranges(bytes).join(", "));
+            }
+        }
+
+        w!("}}");
+        out
+    }
+
+    /// A description of a single state, for when the `Debug` output of the whole DFA is too
+    ///  much: whether it's final and which patterns end there, and the bytes that lead to each
+    ///  state other than the stuck state.
+    pub fn debug_state(&self, state: StateNumber) -> String {
+        let mut targets: BTreeMap<StateNumber, BTreeSet<u8>> = BTreeMap::new();
+        for (byte, &to) in self.states[state].transitions.iter().enumerate() {
+            if to != STUCK {
+                targets.entry(to).or_default().insert(byte as u8);
+            }
+        }
+        let transition_count: usize = targets.values().map(|bytes| bytes.len()).sum();
+
+        let mut out = state.to_string();
+        if state == START {
+            out.push_str(" (start)");
+        }
+        if state == STUCK {
+            out.push_str(" (stuck)");
+        }
+        if self.finals[state] {
+            out.push_str(&format!(
+                ": final, pattern ends {:?}",
+                self.states[state].pattern_ends
+            ));
+        } else {
+            out.push_str(": not final");
+        }
+        out.push_str(&format!(", {} transitions\n", transition_count));
+        for (to, bytes) in targets {
+            out.push_str(&format!(
+                "  -> {} on {}\n",
+                to,
+                implode_ranges(bytes).join(", ")
+            ));
+        }
+        out
+    }
+
+    /// Runs the DFA over `bytes` starting from `*state`, and leaves `*state` at the state after
+    ///  the last byte, so the next chunk of a bigger input can continue from there.
+    /// Returns the patterns that end anywhere in this chunk, in order of their end position.
+    pub fn apply_stateful(&self, state: &mut StateNumber, bytes: &[u8]) -> Vec<PatternNumber> {
+        let mut matches = Vec::new();
+        for &byte in bytes {
+            *state = self.states[*state].transitions[byte as usize];
+            if *state == STUCK {
+                break;
+            }
+            matches.extend(&self.states[*state].pattern_ends);
+        }
+        matches
+    }
+
+    /// Iterates over the inputs of at most `max_len` bytes that end in a final state, shortest
+    ///  first. Useful for generating test cases, but note that the number of paths can grow
+    ///  with the alphabet size to the power of `max_len`.
+    pub fn iter_accepted_paths(&self, max_len: usize) -> AcceptedPaths<'_> {
+        let mut queue = VecDeque::new();
+        queue.push_back((START, Vec::new()));
+        AcceptedPaths {
+            dfa: self,
+            max_len,
+            queue,
+        }
+    }
+
+    /// The minimal DFA that reports the same matches, computed with Hopcroft's partition
+    ///  refinement algorithm. States are only merged if they end the same patterns, so this
+    ///  keeps the pattern numbers intact, not just the language. Unreachable states are dropped.
+    pub fn minimize(&self) -> DFA {
+        let mut reachable = self.reachable_states();
+        if !reachable.contains(&STUCK) {
+            reachable.push(STUCK);
+        }
+
+        // Incoming transitions per state, as (byte, from) pairs
+        let mut incoming: Vec<Vec<(Input, StateNumber)>> = vec![Vec::new(); self.states.len()];
+        for &from in &reachable {
+            for (byte, &to) in self.states[from].transitions.iter().enumerate() {
+                incoming[to].push((byte as Input, from));
+            }
+        }
+
+        // The initial partition: states that end the same patterns
+        let mut initial: BTreeMap<(bool, &[PatternNumber]), Vec<StateNumber>> = BTreeMap::new();
+        for &state in &reachable {
+            let key = (
+                self.finals[state],
+                self.states[state].pattern_ends.as_slice(),
+            );
+            initial.entry(key).or_default().push(state);
+        }
+        let mut blocks: Vec<Vec<StateNumber>> = initial.into_values().collect();
+        let mut block_of = vec![usize::MAX; self.states.len()];
+        for (b, block) in blocks.iter().enumerate() {
+            for &state in block {
+                block_of[state] = b;
+            }
+        }
+
+        let mut worklist: Vec<usize> = (0..blocks.len()).collect();
+        let mut in_worklist = vec![true; blocks.len()];
+        let mut marked = BitVec::from_elem(self.states.len(), false);
+        let mut preimages: Vec<Vec<StateNumber>> = vec![Vec::new(); 256];
+        while let Some(splitter) = worklist.pop() {
+            in_worklist[splitter] = false;
+            for &to in &blocks[splitter] {
+                for &(byte, from) in &incoming[to] {
+                    preimages[byte as usize].push(from);
+                }
+            }
+            for preimage in preimages.iter_mut() {
+                // The states of every block that go into the splitter on this byte
+                let mut touched: BTreeMap<usize, Vec<StateNumber>> = BTreeMap::new();
+                for &state in preimage.iter() {
+                    marked.set(state, true);
+                    touched.entry(block_of[state]).or_default().push(state);
+                }
+                for (b, inside) in touched {
+                    if inside.len() == blocks[b].len() {
+                        continue;
+                    }
+                    blocks[b].retain(|&state| !marked[state]);
+                    let new_b = blocks.len();
+                    for &state in &inside {
+                        block_of[state] = new_b;
+                    }
+                    blocks.push(inside);
+                    // If `b` is still waiting, both halves have to be processed. Otherwise only
+                    //  the smaller half needs to be.
+                    if in_worklist[b] || blocks[new_b].len() <= blocks[b].len() {
+                        worklist.push(new_b);
+                        in_worklist.push(true);
+                    } else {
+                        worklist.push(b);
+                        in_worklist[b] = true;
+                        in_worklist.push(false);
+                    }
+                }
+                for &state in preimage.iter() {
+                    marked.set(state, false);
+                }
+                preimage.clear();
+            }
+        }
+
+        // Number the blocks, keeping STUCK and START in their place. If the start state is
+        //  equivalent to the stuck state, START becomes a copy of STUCK.
+        let mut new_num = vec![usize::MAX; blocks.len()];
+        new_num[block_of[STUCK]] = STUCK;
+        new_num[block_of[START]] = new_num[block_of[START]].min(START);
+        let mut next_num = 2;
+        for num in new_num.iter_mut().filter(|num| **num == usize::MAX) {
+            *num = next_num;
+            next_num += 1;
+        }
+
+        let mut representatives = vec![STUCK; next_num];
+        for (b, block) in blocks.iter().enumerate() {
+            representatives[new_num[b]] = block[0];
+        }
+        representatives[START] = START;
+        let states: Vec<DFAState> = representatives
+            .iter()
+            .map(|&rep| {
+                let mut transitions = [STUCK; 256];
+                for (to, &old_to) in transitions
+                    .iter_mut()
+                    .zip(self.states[rep].transitions.iter())
+                {
+                    *to = new_num[block_of[old_to]];
+                }
+                DFAState::new(transitions, self.states[rep].pattern_ends.clone())
+            })
+            .collect();
+        let finals = BitVec::from_fn(states.len(), |i| self.finals[representatives[i]]);
+
+        DFA::new(states.into_boxed_slice(), finals, self.dict.clone())
+            .with_dict_index(self.dict_index.clone())
+    }
+
+    /// The DFA that accepts the inputs that both `self` and `other` accept. Final states report
+    ///  the patterns of `self`.
+    pub fn intersect(&self, other: &DFA) -> DFA {
+        let (stuck1, stuck2) = (self.finals[STUCK], other.finals[STUCK]);
+        self.product(
+            other,
+            |fin1, fin2| fin1 && fin2,
+            |s1, s2| (s1 == STUCK && !stuck1) || (s2 == STUCK && !stuck2),
+        )
+    }
+
+    /// The DFA that accepts the inputs that `self` accepts but `other` doesn't. Final states
+    ///  report the patterns of `self`.
+    pub fn difference(&self, other: &DFA) -> DFA {
+        let (stuck1, stuck2) = (self.finals[STUCK], other.finals[STUCK]);
+        self.product(
+            other,
+            |fin1, fin2| fin1 && !fin2,
+            |s1, s2| (s1 == STUCK && !stuck1) || (s2 == STUCK && stuck2),
+        )
+    }
+
+    /// The DFA that accepts exactly the inputs that this one doesn't, by flipping which states
+    ///  are final. The stuck state becomes final too, so any input that this DFA gets stuck on
+    ///  is accepted. There are no patterns to report for the complement, so none of the states
+    ///  have pattern ends; use `accepts` rather than `apply` or `find`.
+    pub fn complement(&self) -> DFA {
+        let states: Vec<DFAState> = self
+            .states
+            .iter()
+            .map(|state| DFAState::new(state.transitions, Vec::new()))
+            .collect();
+        let mut finals = self.finals.clone();
+        finals.negate();
+        DFA::new(states.into_boxed_slice(), finals, self.dict.clone())
+            .with_dict_index(self.dict_index.clone())
+    }
+
+    /// Whether the DFA ends in a final state after `input`. Like `apply`, this stops as soon as
+    ///  the stuck state is reached. That remains correct for a `complement`, where the stuck
+    ///  state is final: it still goes to itself on every byte, so the outcome is known.
+    pub fn accepts(&self, input: &[Input]) -> bool {
+        let mut cur_state = START;
+        for &byte in input {
+            cur_state = self.states[cur_state].transitions[byte as usize];
+            if cur_state == STUCK {
+                break;
+            }
+        }
+        self.finals[cur_state]
+    }
+
+    /// Whether any final state can be reached from the start state, i.e. whether the DFA
+    ///  accepts any input at all
+    pub fn has_reachable_accepting_state(&self) -> bool {
+        let mut seen = BitVec::from_elem(self.states.len(), false);
+        let mut queue = VecDeque::new();
+        seen.set(START, true);
+        queue.push_back(START);
+        while let Some(state) = queue.pop_front() {
+            if self.finals[state] {
+                return true;
+            }
+            for &to in self.states[state].transitions.iter() {
+                if !seen[to] {
+                    seen.set(to, true);
+                    queue.push_back(to);
+                }
+            }
+        }
+        false
+    }
+
+    /// Removes the dead states: the states from which no final state can be reached. The
+    ///  transitions to them go to the stuck state instead, which doesn't change the accepted
+    ///  language or the matches. The start and stuck states are always kept.
+    pub fn prune_dead_states(&mut self) {
+        // The components come sinks first, so the successors of a component are decided before it
+        let sccs = tarjan_sccs(self.states.len(), |state| {
+            let targets: BTreeSet<StateNumber> =
+                self.states[state].transitions.iter().cloned().collect();
+            targets.into_iter().collect()
+        });
+        let mut live = BitVec::from_elem(self.states.len(), false);
+        for scc in &sccs {
+            let is_live = scc.iter().any(|&state| {
+                self.finals[state] || self.states[state].transitions.iter().any(|&to| live[to])
+            });
+            if is_live {
+                for &state in scc {
+                    live.set(state, true);
+                }
+            }
+        }
+
+        let mut renumber = vec![STUCK; self.states.len()];
+        let mut kept = 0;
+        for state in 0..self.states.len() {
+            if live[state] || state == STUCK || state == START {
+                renumber[state] = kept;
+                kept += 1;
+            }
+        }
+
+        let states = mem::take(&mut self.states).into_vec();
+        let mut finals = BitVec::with_capacity(kept);
+        let mut pruned = Vec::with_capacity(kept);
+        for (state, mut dfa_state) in states.into_iter().enumerate() {
+            if !(live[state] || state == STUCK || state == START) {
+                continue;
+            }
+            for to in dfa_state.transitions.iter_mut() {
+                *to = if live[*to] { renumber[*to] } else { STUCK };
+            }
+            finals.push(self.finals[state]);
+            pruned.push(dfa_state);
+        }
+        self.states = pruned.into_boxed_slice();
+        self.finals = finals;
+    }
+
+    /// The same automaton as `intersect`, but final states report the patterns of both `self`
+    ///  and `other`: the dictionary of `other` is appended to that of `self`, and its pattern
+    ///  numbers are shifted up past those of `self`. That's the only difference; use `intersect`
+    ///  when only the patterns of `self` matter, e.g. to restrict the matches of `self`.
+    pub fn intersection(&self, other: &DFA) -> DFA {
+        let (stuck1, stuck2) = (self.finals[STUCK], other.finals[STUCK]);
+        self.product_with(
+            other,
+            |fin1, fin2| fin1 && fin2,
+            |s1, s2| (s1 == STUCK && !stuck1) || (s2 == STUCK && !stuck2),
+            true,
+        )
+    }
+
+    /// The DFA that accepts the inputs that `self` or `other` accepts, reporting the patterns
+    ///  of both like `intersection` does.
+    pub fn union(&self, other: &DFA) -> DFA {
+        let (stuck1, stuck2) = (self.finals[STUCK], other.finals[STUCK]);
+        self.product_with(
+            other,
+            |fin1, fin2| fin1 || fin2,
+            |s1, s2| (s1 == STUCK && !stuck1) && (s2 == STUCK && !stuck2),
+            true,
+        )
+    }
+
+    // The product construction over the pairs of states reachable from (START, START). A pair
+    //  is final if `accepting` holds for the finality of its states, and pairs that are `dead`
+    //  are all merged into the stuck state, so they must never be final.
+    fn product<A, D>(&self, other: &DFA, accepting: A, dead: D) -> DFA
+    where
+        A: Fn(bool, bool) -> bool,
+        D: Fn(StateNumber, StateNumber) -> bool,
+    {
+        self.product_with(other, accepting, dead, false)
+    }
+
+    // `product`, where final pairs report only the patterns of `self`, or with `both_dicts`
+    //  also those of `other`, after the patterns of `self`
+    fn product_with<A, D>(&self, other: &DFA, accepting: A, dead: D, both_dicts: bool) -> DFA
+    where
+        A: Fn(bool, bool) -> bool,
+        D: Fn(StateNumber, StateNumber) -> bool,
+    {
+        // Pairs are keyed by `s1 * other.states.len() + s2`, numbered in the order they're found
+        let key = |(s1, s2): (StateNumber, StateNumber)| s1 * other.states.len() + s2;
+        let mut pairs = vec![(STUCK, STUCK), (START, START)];
+        let mut pair_nums: HashMap<usize, StateNumber> = HashMap::new();
+        pair_nums.insert(key((STUCK, STUCK)), STUCK);
+        pair_nums.insert(key((START, START)), START);
+
+        let offset = self.pattern_number_bound();
+        let mut states = Vec::new();
+        let mut finals = BitVec::new();
+        let mut num = 0;
+        while num < pairs.len() {
+            let (s1, s2) = pairs[num];
+            let mut transitions = [STUCK; 256];
+            if num != STUCK {
+                for (byte, to) in transitions.iter_mut().enumerate() {
+                    let pair = (
+                        self.states[s1].transitions[byte],
+                        other.states[s2].transitions[byte],
+                    );
+                    if dead(pair.0, pair.1) {
+                        continue;
+                    }
+                    *to = *pair_nums.entry(key(pair)).or_insert_with(|| {
+                        pairs.push(pair);
+                        pairs.len() - 1
+                    });
+                }
+            }
+            let fin = accepting(self.finals[s1], other.finals[s2]);
+            let mut pattern_ends = Vec::new();
+            if fin && self.finals[s1] {
+                pattern_ends.extend_from_slice(&self.states[s1].pattern_ends);
+            }
+            if fin && both_dicts && other.finals[s2] {
+                pattern_ends.extend(other.states[s2].pattern_ends.iter().map(|p| p + offset));
+            }
+            states.push(DFAState::new(transitions, pattern_ends));
+            finals.push(fin);
+            num += 1;
+        }
+
+        if !both_dicts {
+            return DFA::new(states.into_boxed_slice(), finals, self.dict.clone())
+                .with_dict_index(self.dict_index.clone());
+        }
+        let mut dict = self.dict.clone();
+        dict.extend(other.dict.iter().cloned());
+        let dict_index = if self.dict_index.is_none() && other.dict_index.is_none() {
+            None
+        } else {
+            let ids = |dfa: &DFA| -> Vec<(PatternNumber, usize)> {
+                match &dfa.dict_index {
+                    Some(dict_index) => dict_index.iter().map(|(&id, &idx)| (id, idx)).collect(),
+                    None => (0..dfa.dict.len()).map(|idx| (idx, idx)).collect(),
+                }
+            };
+            let mut dict_index: HashMap<PatternNumber, usize> = ids(self).into_iter().collect();
+            for (id, idx) in ids(other) {
+                dict_index.insert(id + offset, idx + self.dict.len());
+            }
+            Some(dict_index)
+        };
+        DFA::new(states.into_boxed_slice(), finals, dict).with_dict_index(dict_index)
+    }
+
+    // One more than the highest pattern number
+    fn pattern_number_bound(&self) -> PatternNumber {
+        match &self.dict_index {
+            Some(dict_index) => dict_index.keys().max().map_or(0, |&id| id + 1),
+            None => self.dict.len(),
+        }
+    }
+}
+
+/// Whether `a` and `b` accept the same inputs, by the algorithm of Hopcroft and Karp: pairs of
+///  states that are reached by the same input are merged, from the start states onwards, and
+///  the DFAs differ as soon as a merged pair has one final and one non-final state. Which
+///  patterns are reported doesn't matter, only which inputs are accepted.
+pub fn are_equivalent(a: &DFA, b: &DFA) -> bool {
+    // The states of `b` are numbered after those of `a` in the union-find
+    let offset = a.states.len();
+    let mut parent: Vec<usize> = (0..offset + b.states.len()).collect();
+    let mut stack = Vec::new();
+    union(&mut parent, START, offset + START);
+    stack.push((START, START));
+    while let Some((s1, s2)) = stack.pop() {
+        if a.finals[s1] != b.finals[s2] {
+            return false;
+        }
+        for byte in 0..256 {
+            let to1 = a.states[s1].transitions[byte];
+            let to2 = b.states[s2].transitions[byte];
+            if union(&mut parent, to1, offset + to2) {
+                stack.push((to1, to2));
+            }
+        }
+    }
+    true
+}
+
+/// Whether every input that `a` accepts is also accepted by `b`, i.e. whether the
+///  intersection of `a` with the complement of `b` accepts nothing
+pub fn is_language_subset(a: &DFA, b: &DFA) -> bool {
+    !a.intersection(&b.complement())
+        .has_reachable_accepting_state()
+}
+
+fn find(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+// Returns whether `x` and `y` were in different sets before
+fn union(parent: &mut [usize], x: usize, y: usize) -> bool {
+    let (x, y) = (find(parent, x), find(parent, y));
+    parent[x] = y;
+    x != y
+}
+
+impl Default for DfaBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DfaBuilder {
+    /// A builder with only the stuck and start states
+    pub fn new() -> Self {
+        DfaBuilder {
+            transitions: vec![[STUCK; 256]; 2],
+            pattern_ends: vec![Vec::new(); 2],
+            finals: BitVec::from_elem(2, false),
+            dict: Vec::new(),
+            dict_index: None,
+        }
+    }
+
+    /// A builder with the states, transitions and dictionary of `dfa`, to edit it
+    pub fn from_dfa(dfa: &DFA) -> Self {
+        DfaBuilder {
+            transitions: dfa.states.iter().map(|state| state.transitions).collect(),
+            pattern_ends: dfa
+                .states
+                .iter()
+                .map(|state| state.pattern_ends.clone())
+                .collect(),
+            finals: dfa.finals.clone(),
+            dict: dfa.dict.clone(),
+            dict_index: dfa.dict_index.clone(),
+        }
+    }
+
+    /// Sets the dictionary, which `find` needs for the start of a match. Every pattern end
+    ///  has to be in it.
+    pub fn dict(&mut self, dict: Vec<Vec<Input>>) -> &mut Self {
+        self.dict = dict;
+        self.dict_index = None;
+        self
+    }
+
+    pub fn new_state(&mut self) -> StateNumber {
+        self.transitions.push([STUCK; 256]);
+        self.pattern_ends.push(Vec::new());
+        self.finals.push(false);
+        self.transitions.len() - 1
+    }
+
+    /// Adds a transition, unless `from` already goes to another state on `on`
+    pub fn add_transition(
+        &mut self,
+        from: StateNumber,
+        on: Input,
+        to: StateNumber,
+    ) -> Result<(), BuildError> {
+        assert!(from < self.transitions.len());
+        assert!(to < self.transitions.len());
+        let cur = &mut self.transitions[from][on as usize];
+        if *cur != STUCK && *cur != to {
+            return Err(BuildError::DuplicateTransition { from, on });
+        }
+        *cur = to;
+        Ok(())
+    }
+
+    /// Makes `state` final, with `pattern_end` as one of the patterns that end there
+    pub fn mark_accepting(&mut self, state: StateNumber, pattern_end: PatternNumber) {
+        assert!(state < self.transitions.len());
+        self.finals.set(state, true);
+        if !self.pattern_ends[state].contains(&pattern_end) {
+            self.pattern_ends[state].push(pattern_end);
+        }
+    }
+
+    /// The `DFA`, if every pattern end is in the dictionary. So a DFA with pattern ends needs a
+    ///  dictionary, which `find` uses for the start of a match.
+    pub fn build(self) -> Result<DFA, BuildError> {
+        let dict_len = self.dict.len();
+        let dict_index = &self.dict_index;
+        let known_pattern = |patt_no: &PatternNumber| match dict_index {
+            Some(dict_index) => dict_index.contains_key(patt_no),
+            None => *patt_no < dict_len,
+        };
+        if !self
+            .pattern_ends
+            .iter()
+            .all(|pattern_ends| pattern_ends.iter().all(known_pattern))
+        {
+            return Err(BuildError::InvalidPatternNumber);
+        }
+
+        let states: Vec<DFAState> = self
+            .transitions
+            .into_iter()
+            .zip(self.pattern_ends)
+            .map(|(transitions, pattern_ends)| DFAState::new(transitions, pattern_ends))
+            .collect();
+        Ok(DFA::new(states.into_boxed_slice(), self.finals, self.dict)
+            .with_dict_index(self.dict_index))
+    }
+}
+
+/// Iterator over the inputs accepted by a `DFA`, created by `DFA::iter_accepted_paths`.
+pub struct AcceptedPaths<'a> {
+    dfa: &'a DFA,
+    max_len: usize,
+    queue: VecDeque<(StateNumber, Vec<Input>)>,
+}
+
+impl<'a> Iterator for AcceptedPaths<'a> {
+    type Item = Vec<Input>;
+
+    fn next(&mut self) -> Option<Vec<Input>> {
+        while let Some((state, path)) = self.queue.pop_front() {
+            if path.len() < self.max_len {
+                for (byte, &nxt_state) in self.dfa.states[state].transitions.iter().enumerate() {
+                    if nxt_state != STUCK {
+                        let mut nxt_path = path.clone();
+                        nxt_path.push(byte as Input);
+                        self.queue.push_back((nxt_state, nxt_path));
+                    }
+                }
+            }
+            if self.dfa.finals[state] {
+                return Some(path);
+            }
+        }
+        None
+    }
 }
 
 impl Automaton<Input> for DFA {
     type State = StateNumber;
+    type Payload = PatternNumber;
 
     fn start_state(&self) -> Self::State {
         START
@@ -98,6 +1093,15 @@ impl Automaton<Input> for DFA {
         STUCK
     }
 
+    #[inline]
+    fn state_is_stuck(&self, &state: &Self::State) -> bool {
+        state == STUCK
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        Some(self.dict.iter().map(|bytes| bytes.len()).max().unwrap_or(0))
+    }
+
     #[inline]
     fn next_state(&self, &state: &Self::State, &input: &Input) -> Self::State {
         self.states[state].transitions[input as usize]
@@ -108,12 +1112,17 @@ impl Automaton<Input> for DFA {
         patt_no_offset < self.states[state].pattern_ends.len()
     }
 
+    #[inline]
+    fn state_is_final(&self, &state: &Self::State) -> bool {
+        self.finals[state]
+    }
+
     #[inline]
     fn get_match(&self, &state: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match {
         let patt_no = self.states[state].pattern_ends[patt_no_offset];
         Match {
-            patt_no,
-            start: text_offset - self.dict[patt_no].len(),
+            payload: patt_no,
+            start: text_offset - pattern_len(&self.dict, &self.dict_index, patt_no),
             end: text_offset,
         }
     }
@@ -134,8 +1143,41 @@ impl DDFAState {
 }
 
 impl DDFA {
-    fn new(states: Box<[DDFAState]>, dict: Vec<Vec<Input>>) -> Self {
-        DDFA { states, dict }
+    fn new(
+        states: Box<[DDFAState]>,
+        dict: Vec<Vec<Input>>,
+        dict_index: Option<HashMap<PatternNumber, usize>>,
+    ) -> Self {
+        DDFA {
+            states,
+            dict,
+            dict_index,
+        }
+    }
+
+    /// Rebuilds the `DFA` that this was made from, turning the state pointers back into state
+    ///  numbers.
+    pub fn into_dfa_clone(&self) -> DFA {
+        let states_start: *const DDFAState = self.states.as_ptr();
+        let states: Vec<DFAState> = self
+            .states
+            .iter()
+            .map(|state| {
+                let mut transitions = [STUCK; 256];
+                for (to, &ptr) in transitions.iter_mut().zip(state.transitions.iter()) {
+                    *to = (ptr as usize - states_start as usize) / mem::size_of::<DDFAState>();
+                }
+                DFAState::new(transitions, state.pattern_ends.clone())
+            })
+            .collect();
+        let finals = BitVec::from_fn(self.states.len(), |i| self.states[i].is_final);
+        DFA::new(states.into_boxed_slice(), finals, self.dict.clone())
+            .with_dict_index(self.dict_index.clone())
+    }
+
+    /// The DOT graph of this DDFA, numbering the states by their position, as in `DFA::dot`
+    pub fn dot(&self, options: DotOptions) -> String {
+        self.into_dfa_clone().dot(options)
     }
 
     pub fn apply(&self, input: &[u8]) -> Vec<PatternNumber> {
@@ -153,6 +1195,7 @@ impl DDFA {
 
 impl Automaton<Input> for DDFA {
     type State = *const DDFAState;
+    type Payload = PatternNumber;
 
     fn start_state(&self) -> Self::State {
         &self.states[START]
@@ -162,9 +1205,18 @@ impl Automaton<Input> for DDFA {
         &self.states[STUCK]
     }
 
+    #[inline]
+    fn state_is_stuck(&self, &state: &Self::State) -> bool {
+        std::ptr::eq(state, &self.states[STUCK])
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        Some(self.dict.iter().map(|bytes| bytes.len()).max().unwrap_or(0))
+    }
+
     #[inline]
     fn next_state(&self, &state: &Self::State, &input: &Input) -> Self::State {
-        unsafe { *(*state).transitions.get_unchecked(input as usize) }
+        unsafe { *(*state).transitions.as_ref().get_unchecked(input as usize) }
     }
 
     #[inline]
@@ -172,12 +1224,82 @@ impl Automaton<Input> for DDFA {
         patt_no_offset < unsafe { (*state).pattern_ends.len() }
     }
 
+    #[inline]
+    fn state_is_final(&self, &state: &Self::State) -> bool {
+        unsafe { (*state).is_final }
+    }
+
     #[inline]
     fn get_match(&self, &state: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match {
-        let patt_no = unsafe { *(*state).pattern_ends.get_unchecked(patt_no_offset) };
+        let patt_no = unsafe {
+            *(*state)
+                .pattern_ends
+                .as_slice()
+                .get_unchecked(patt_no_offset)
+        };
         Match {
-            patt_no,
-            start: text_offset - self.dict[patt_no].len(),
+            payload: patt_no,
+            start: text_offset - pattern_len(&self.dict, &self.dict_index, patt_no),
+            end: text_offset,
+        }
+    }
+}
+
+impl SafeDDFA {
+    pub fn apply(&self, input: &[u8]) -> Vec<PatternNumber> {
+        let mut cur_state = START;
+        for &byte in input {
+            cur_state = self.states[cur_state].transitions[byte as usize] as usize;
+            if cur_state == STUCK {
+                break;
+            }
+        }
+        self.states[cur_state].pattern_ends.clone()
+    }
+}
+
+impl Automaton<Input> for SafeDDFA {
+    type State = StateNumber;
+    type Payload = PatternNumber;
+
+    fn start_state(&self) -> Self::State {
+        START
+    }
+
+    fn stuck_state(&self) -> Self::State {
+        STUCK
+    }
+
+    #[inline]
+    fn state_is_stuck(&self, &state: &Self::State) -> bool {
+        state == STUCK
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        Some(self.dict.iter().map(|bytes| bytes.len()).max().unwrap_or(0))
+    }
+
+    #[inline]
+    fn next_state(&self, &state: &Self::State, &input: &Input) -> Self::State {
+        self.states[state].transitions[input as usize] as usize
+    }
+
+    #[inline]
+    fn has_match(&self, &state: &Self::State, patt_no_offset: usize) -> bool {
+        patt_no_offset < self.states[state].pattern_ends.len()
+    }
+
+    #[inline]
+    fn state_is_final(&self, &state: &Self::State) -> bool {
+        self.states[state].is_final
+    }
+
+    #[inline]
+    fn get_match(&self, &state: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match {
+        let patt_no = self.states[state].pattern_ends[patt_no_offset];
+        Match {
+            payload: patt_no,
+            start: text_offset - pattern_len(&self.dict, &self.dict_index, patt_no),
             end: text_offset,
         }
     }
@@ -211,10 +1333,7 @@ macro_rules! debug_impl {
                         write!(f, "\n")?;
                     }
                     let mut last_c = 0;
-                    let mut iter = (*state.transitions)
-                        .into_iter()
-                        .enumerate()
-                        .peekable();
+                    let mut iter = state.transitions.iter().enumerate().peekable();
                     while let Some((c, tr)) = iter.next() {
                         if let Some(&(c2, tr2)) = iter.peek() {
                             if tr == tr2 {
@@ -247,9 +1366,7 @@ debug_impl!(
         dfa.finals[i]
     },
     #[allow(unused_variables)]
-    fn compute_start(dfa: &DFA) -> () {
-        ()
-    },
+    fn compute_start(dfa: &DFA) {},
     #[allow(unused_variables)]
     fn compute_tr_no(tr: &StateNumber, start: ()) -> &StateNumber {
         tr
@@ -272,9 +1389,12 @@ debug_impl!(
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::ndfa::NDFA;
     use crate::nfa::NFA;
+    use crate::util::pseudo_random;
 
-    static BASIC_DICTIONARY: &'static [&'static str] = &["a", "ab", "bab", "bc", "bca", "c", "caa"];
+    static BASIC_DICTIONARY: &[&str] = &["a", "ab", "bab", "bc", "bca", "c", "caa"];
 
     #[test]
     fn basic_ignore_prefixes() {
@@ -326,11 +1446,30 @@ mod tests {
         assert!(!dfa.apply("abb".as_bytes()).is_empty());
     }
 
+    #[test]
+    fn iter_accepted_paths() {
+        let dfa = NFA::from_dictionary(["a", "ab", "bc"])
+            .powerset_construction()
+            .into_dfa()
+            .unwrap();
+        let paths: Vec<Vec<u8>> = dfa.iter_accepted_paths(2).collect();
+        assert_eq!(paths, vec![b"a".to_vec(), b"ab".to_vec(), b"bc".to_vec()]);
+        assert_eq!(dfa.iter_accepted_paths(1).count(), 1);
+
+        let dfa = NFA::from_dictionary(BASIC_DICTIONARY)
+            .powerset_construction()
+            .into_dfa()
+            .unwrap();
+        for path in dfa.iter_accepted_paths(3) {
+            assert!(!dfa.apply(&path).is_empty());
+        }
+    }
+
     use crate::automaton::Automaton;
     use std::iter;
 
     fn haystack_same(letter: char) -> String {
-        iter::repeat(letter).take(10000).collect()
+        iter::repeat_n(letter, 10000).collect()
     }
 
     #[test]
@@ -345,7 +1484,7 @@ mod tests {
         assert!(dfa.find(haystack.as_bytes()).next().is_none());
     }
 
-    static HAYSTACK_SHERLOCK: &'static str = include_str!("../benches/sherlock.txt");
+    static HAYSTACK_SHERLOCK: &str = include_str!("../benches/sherlock.txt");
 
     #[test]
     fn from_bench_sherlock_alt1() {
@@ -360,4 +1499,606 @@ mod tests {
 
         assert_eq!(count, dfa.find(haystack.as_bytes()).count());
     }
+
+    #[test]
+    fn reachable_states() {
+        let dfa = NFA::from_dictionary(BASIC_DICTIONARY)
+            .powerset_construction()
+            .into_dfa()
+            .unwrap();
+        let reachable = dfa.reachable_states();
+        assert_eq!(reachable[0], START);
+        assert_eq!(reachable.len(), dfa.states.len());
+        assert!(dfa.unreachable_states().is_empty());
+
+        // Adding a state to a transition that already exists leaves DFA states behind
+        let mut ndfa = NDFA::new();
+        let a = ndfa.new_state(START, b'a');
+        ndfa.new_state(START, b'a');
+        ndfa.mark_final(a);
+        let dfa = ndfa.finalize();
+        assert!(!dfa.unreachable_states().is_empty());
+        let dfa = ndfa.compact().finalize();
+        assert!(dfa.unreachable_states().is_empty());
+    }
+
+    #[test]
+    fn apply_stateful_packets() {
+        let mut nfa = NFA::from_dictionary(vec!["Sherlock", "Street"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+
+        let mut state = dfa.start_state();
+        let count: usize = HAYSTACK_SHERLOCK
+            .as_bytes()
+            .chunks(256)
+            .map(|packet| dfa.apply_stateful(&mut state, packet).len())
+            .sum();
+        assert_eq!(count, dfa.find(HAYSTACK_SHERLOCK.as_bytes()).count());
+        assert_eq!(count, 158);
+    }
+
+    #[test]
+    fn matches_reset() {
+        let mut nfa = NFA::from_dictionary(vec!["Sherlock", "Street"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+
+        let first = dfa.find(haystack).next();
+        let mut matches = dfa.find(haystack);
+        assert_eq!(matches.by_ref().count(), 158);
+        matches.reset();
+        assert_eq!(matches.next(), first);
+        assert_eq!(matches.count(), 157);
+    }
+
+    #[test]
+    fn find_from_iter_sherlock() {
+        let mut nfa = NFA::from_dictionary(vec!["Sherlock", "Holmes", "Watson"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+
+        let haystack: Vec<u8> = HAYSTACK_SHERLOCK.bytes().collect();
+        let ends: Vec<(usize, usize)> = dfa.find(&haystack).map(|m| (m.payload, m.end)).collect();
+        let iter_ends: Vec<(usize, usize)> = dfa
+            .find_from_iter(haystack.into_iter())
+            .map(|m| (m.payload, m.end))
+            .collect();
+        assert!(!ends.is_empty());
+        assert_eq!(ends, iter_ends);
+    }
+
+    #[test]
+    fn minimize_merges_equivalent_states() {
+        // 2 and 3 are equivalent, 5 is unreachable
+        let mut rows = vec![[STUCK; 256]; 6];
+        rows[START][b'a' as usize] = 2;
+        rows[START][b'b' as usize] = 3;
+        rows[2][b'c' as usize] = 4;
+        rows[3][b'c' as usize] = 4;
+        rows[5][b'c' as usize] = 4;
+        let pattern_ends = vec![vec![], vec![], vec![], vec![], vec![0], vec![]];
+        let states: Vec<DFAState> = rows
+            .into_iter()
+            .zip(pattern_ends)
+            .map(|(row, ends)| DFAState::new(row, ends))
+            .collect();
+        let finals = BitVec::from_fn(6, |i| i == 4);
+        let dfa = DFA::new(states.into_boxed_slice(), finals, vec![b"ac".to_vec()]);
+
+        let min = dfa.minimize();
+        assert_eq!(min.states.len(), 4);
+        for input in &["ac", "bc", "a", "cc", "acc", ""] {
+            assert_eq!(min.apply(input.as_bytes()), dfa.apply(input.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn minimize() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        nfa.ignore_postfixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let min = dfa.minimize();
+        assert!(min.states.len() < dfa.states.len());
+        for input in &["a", "bca", "xbcax", "cab", "abab", "bb", "xyz", ""] {
+            assert_eq!(min.apply(input.as_bytes()), dfa.apply(input.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn into_minimal_dfa() {
+        let mut nfa = NFA::from_dictionary(vec!["Sherlock", "Street"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let min = nfa.into_minimal_dfa().unwrap();
+        assert!(min.states.len() <= dfa.states.len());
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        assert_eq!(min.find(haystack).count(), 158);
+        assert!(min.find(haystack).eq(dfa.find(haystack)));
+
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        nfa.ignore_postfixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let min = nfa.into_minimal_dfa().unwrap();
+        assert!(min.states.len() < dfa.states.len());
+        for input in &["bca", "xbcax", "cab", "abab", "bb", ""] {
+            assert_eq!(min.apply(input.as_bytes()), dfa.apply(input.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn intersect_difference() {
+        let accepted = |dfa: &DFA| -> Vec<String> {
+            dfa.iter_accepted_paths(4)
+                .map(|path| String::from_utf8(path).unwrap())
+                .collect()
+        };
+        let dfa1 = NFA::from_dictionary(["ab", "abc", "b"]).into_dfa().unwrap();
+        let dfa2 = NFA::from_dictionary(["abc", "b", "xyz"])
+            .into_dfa()
+            .unwrap();
+
+        let both = dfa1.intersect(&dfa2);
+        assert_eq!(accepted(&both), vec!["b", "abc"]);
+        assert_eq!(both.apply(b"abc"), vec![1]);
+        assert_eq!(accepted(&dfa1.difference(&dfa2)), vec!["ab"]);
+        assert_eq!(accepted(&dfa2.difference(&dfa1)), vec!["xyz"]);
+        assert!(accepted(&dfa1.difference(&dfa1)).is_empty());
+
+        let mut nfa = NFA::from_dictionary(["ab"]);
+        nfa.ignore_prefixes();
+        let ends_in_ab = nfa.powerset_construction().into_dfa().unwrap();
+        assert_eq!(accepted(&ends_in_ab.intersect(&dfa1)), vec!["ab"]);
+    }
+
+    #[test]
+    fn complement() {
+        let dfa = NFA::from_dictionary(["abc"]).into_dfa().unwrap();
+        let not_abc = dfa.complement();
+        for input in &["", "a", "ab", "abcd", "xabc", "abd", "cba"] {
+            assert!(!dfa.accepts(input.as_bytes()));
+            assert!(not_abc.accepts(input.as_bytes()));
+        }
+        assert!(dfa.accepts(b"abc"));
+        assert!(!not_abc.accepts(b"abc"));
+        assert!(not_abc.apply(b"x").is_empty());
+
+        let twice = not_abc.complement();
+        assert!(twice.finals == dfa.finals);
+        for input in &["", "a", "ab", "abc", "abcd", "xabc"] {
+            assert_eq!(
+                twice.accepts(input.as_bytes()),
+                dfa.accepts(input.as_bytes())
+            );
+        }
+
+        assert!(dfa.has_reachable_accepting_state());
+        assert!(not_abc.has_reachable_accepting_state());
+        assert!(!dfa.difference(&dfa).has_reachable_accepting_state());
+        // The stuck state of the complement is final, which the product has to respect
+        let only_abc = dfa.intersect(&not_abc.complement());
+        assert!(only_abc.accepts(b"abc") && !only_abc.accepts(b"ab"));
+        let ab = NFA::from_dictionary(["ab"]).into_dfa().unwrap();
+        let not_ab = not_abc.intersect(&ab.complement());
+        assert!(not_ab.accepts(b"xyz") && !not_ab.accepts(b"ab") && !not_ab.accepts(b"abc"));
+        assert!(!ab.difference(&not_abc).has_reachable_accepting_state());
+        assert!(not_abc.difference(&ab).accepts(b"zzz"));
+    }
+
+    #[test]
+    fn intersection_union() {
+        let mut nfa = NFA::from_dictionary(["ab", "bc"]);
+        nfa.ignore_prefixes();
+        let a = nfa.powerset_construction().into_dfa().unwrap();
+        let b = NFA::from_dictionary(["abc", "xbc", "q"])
+            .into_dfa()
+            .unwrap();
+        let inputs = ["", "ab", "bc", "abc", "xbc", "xab", "q", "xq", "abcd", "b"];
+
+        let aa = a.intersection(&a);
+        for input in &inputs {
+            assert_eq!(aa.accepts(input.as_bytes()), a.accepts(input.as_bytes()));
+        }
+
+        let both = a.intersection(&b);
+        let either = a.union(&b);
+        for input in &inputs {
+            let (in_a, in_b) = (a.accepts(input.as_bytes()), b.accepts(input.as_bytes()));
+            assert_eq!(both.accepts(input.as_bytes()), in_a && in_b, "{}", input);
+            assert_eq!(either.accepts(input.as_bytes()), in_a || in_b, "{}", input);
+        }
+        // "bc" is pattern 1 of `a`, "xbc" pattern 1 of `b`, which becomes 2 + 1
+        assert_eq!(both.apply(b"xbc"), vec![1, 3]);
+        assert_eq!(either.apply(b"q"), vec![4]);
+        let m = either.find(b"q").next().unwrap();
+        assert_eq!((m.start, m.end), (0, 1));
+
+        assert!(!a
+            .intersection(&a.complement())
+            .has_reachable_accepting_state());
+        assert!(a.union(&a.complement()).accepts(b"anything"));
+
+        let ids = NFA::from_dictionary_with_ids(["ab"], vec![7])
+            .unwrap()
+            .into_dfa()
+            .unwrap();
+        let either = ids.union(&b);
+        assert_eq!(either.apply(b"ab"), vec![7]);
+        assert_eq!(either.apply(b"abc"), vec![8]);
+        assert_eq!(either.find(b"xbc").next().unwrap().start, 0);
+    }
+
+    #[test]
+    fn dot() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let options = || DotOptions {
+            bold_dict_edges: true,
+            suppress_stuck_state: true,
+            label: Some("basic <dict>".to_string()),
+            show_pattern_ends: true,
+            labels: None,
+        };
+        let dot = dfa.dot(options());
+        assert!(dot.contains("<FONT POINT-SIZE=\"20\">basic &lt;dict&gt;</FONT>"));
+        assert!(dot.contains("    start -> 1;"));
+        assert!(dot.contains(" [peripheries=2] [xlabel=\""));
+        assert!(!dot.contains("⊥"));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let ddfa = nfa
+            .powerset_construction()
+            .into_dfa()
+            .unwrap()
+            .into_ddfa()
+            .unwrap();
+        assert_eq!(ddfa.dot(options()), dot);
+        assert!(!dfa.dot(DotOptions::default()).contains("xlabel"));
+
+        // Only a smoke test, if graphviz is installed
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+        if let Ok(mut child) = Command::new("dot")
+            .arg("-Tsvg")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+        {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(dot.as_bytes())
+                .unwrap();
+            assert!(child.wait().unwrap().success());
+        }
+    }
+
+    #[test]
+    fn to_bytes_from_bytes() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let bytes = dfa.to_bytes();
+        assert_eq!(&bytes[..5], b"DNFA\x01");
+        let loaded = DFA::from_bytes(&bytes).unwrap();
+        assert!(loaded == dfa);
+        let haystack = "xbabcaabcab bca caac".as_bytes();
+        assert!(loaded.find(haystack).eq(dfa.find(haystack)));
+
+        let nfa = NFA::from_dictionary_with_ids(["ab", "b"], vec![10, 20]).unwrap();
+        let dfa = nfa.into_dfa().unwrap();
+        assert!(DFA::from_bytes(&dfa.to_bytes()).unwrap() == dfa);
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert_eq!(DFA::from_bytes(&bad), Err(DeserializeError::BadMagic));
+        let mut bad = bytes.clone();
+        bad[4] = 99;
+        assert_eq!(
+            DFA::from_bytes(&bad),
+            Err(DeserializeError::UnsupportedVersion)
+        );
+        let mut bad = bytes.clone();
+        bad[9] = 0xff;
+        assert_eq!(
+            DFA::from_bytes(&bad),
+            Err(DeserializeError::InvalidStateIndex)
+        );
+        let mut bad = bytes.clone();
+        bad.push(0);
+        assert_eq!(DFA::from_bytes(&bad), Err(DeserializeError::TrailingBytes));
+        // No states, no dictionary and no ids
+        let bad = b"DNFA\x01\0\0\0\0\0\0\0\0\0";
+        assert_eq!(DFA::from_bytes(bad), Err(DeserializeError::MissingStates));
+    }
+
+    #[test]
+    fn from_bytes_truncated() {
+        let dfa = NFA::from_dictionary(BASIC_DICTIONARY).into_dfa().unwrap();
+        let bytes = dfa.to_bytes();
+        for len in (0..bytes.len())
+            .step_by(7)
+            .chain(bytes.len() - 20..bytes.len())
+        {
+            assert_eq!(
+                DFA::from_bytes(&bytes[..len]),
+                Err(DeserializeError::Truncated),
+                "truncated to {} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn debug_state() {
+        let dfa = NFA::from_dictionary(BASIC_DICTIONARY).into_dfa().unwrap();
+        assert_eq!(
+            dfa.debug_state(START),
+            "1 (start): not final, 3 transitions\n  -> 2 on 'a'\n  -> 4 on 'b'\n  -> 9 on 'c'\n"
+        );
+        assert_eq!(
+            dfa.debug_state(2),
+            "2: final, pattern ends [0], 1 transitions\n  -> 3 on 'b'\n"
+        );
+
+        let mut nfa = NFA::from_dictionary(["ab"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        assert!(dfa
+            .debug_state(START)
+            .starts_with("1 (start): not final, 256 transitions\n"));
+    }
+
+    #[test]
+    fn apply_multi() {
+        let inputs: Vec<&[u8]> = vec![b"a", b"bca", b"", b"bb", b"caa"];
+        let nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        let nfa_results = nfa.apply_multi(&inputs);
+        let dfa = nfa.into_dfa().unwrap();
+        let dfa_results = dfa.apply_multi(&inputs);
+        assert_eq!(nfa_results.len(), inputs.len());
+        for (i, input) in inputs.iter().enumerate() {
+            assert_eq!(dfa_results[i], dfa.apply(input));
+            assert_eq!(nfa_results[i], dfa.apply(input));
+        }
+        assert_eq!(dfa_results[1], vec![4]);
+    }
+
+    #[test]
+    fn ddfa_into_dfa_clone() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let ddfa = nfa
+            .powerset_construction()
+            .into_dfa()
+            .unwrap()
+            .into_ddfa()
+            .unwrap();
+        let clone = ddfa.into_dfa_clone();
+        assert_eq!(clone, dfa);
+        assert_eq!(clone.apply(b"xbca"), dfa.apply(b"xbca"));
+    }
+
+    #[test]
+    fn transition_count_and_memory_estimate() {
+        let needles = vec![
+            "Sherlock", "Holmes", "Watson", "Irene", "Adler", "John", "Baker",
+        ];
+        let trie = NFA::from_dictionary(&needles).into_dfa().unwrap();
+        let trie_states = trie.states.len();
+        // Every state but stuck and start has a single incoming transition
+        assert_eq!(trie.non_stuck_transition_count(), trie_states - 2);
+
+        let mut nfa = NFA::from_dictionary(&needles);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let states = dfa.states.len();
+        assert_eq!(dfa.non_stuck_transition_count(), (states - 1) * 256);
+
+        for (dfa, states) in [(trie, trie_states), (dfa, states)] {
+            // The transition tables should be nearly everything
+            let table_bytes = states * 256 * mem::size_of::<StateNumber>();
+            let estimate = dfa.memory_estimate_bytes();
+            assert!(estimate > table_bytes);
+            assert!(estimate < table_bytes + table_bytes / 10);
+        }
+    }
+
+    #[test]
+    fn prune_dead_states() {
+        // "a" is accepted, but after "b" no final state can be reached anymore
+        let mut transitions = vec![[STUCK; 256]; 5];
+        transitions[START][b'a' as usize] = 2;
+        transitions[START][b'b' as usize] = 3;
+        transitions[3][b'c' as usize] = 4;
+        transitions[4] = [4; 256];
+        let states: Vec<DFAState> = transitions
+            .into_iter()
+            .enumerate()
+            .map(|(state, transitions)| {
+                DFAState::new(transitions, if state == 2 { vec![0] } else { vec![] })
+            })
+            .collect();
+        let mut finals = BitVec::from_elem(5, false);
+        finals.set(2, true);
+        let mut dfa = DFA::new(states.into_boxed_slice(), finals, vec![b"a".to_vec()]);
+
+        let original = DFA::from_bytes(&dfa.to_bytes()).unwrap();
+        dfa.prune_dead_states();
+        assert_eq!(dfa.state_count(), 3);
+        for input in &["", "a", "b", "bc", "bcc", "ab", "ba"] {
+            assert_eq!(
+                dfa.accepts(input.as_bytes()),
+                original.accepts(input.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn prune_dead_states_keeps_language() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let original = nfa.powerset_construction().into_dfa().unwrap();
+        let mut dfa = nfa.powerset_construction().into_dfa().unwrap();
+        dfa.prune_dead_states();
+        // Every state can still reach a match after ignore_prefixes
+        assert_eq!(dfa.state_count(), original.state_count());
+        assert_eq!(dfa, original);
+
+        let mut complement = original.complement();
+        complement.prune_dead_states();
+        for input in &["a", "bca", "xbcax", "cab", "bb", "xyz", ""] {
+            assert_eq!(
+                complement.accepts(input.as_bytes()),
+                original.complement().accepts(input.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn safe_ddfa() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let safe = nfa
+            .powerset_construction()
+            .into_dfa()
+            .unwrap()
+            .into_safe_ddfa();
+        for input in &["a", "bca", "xbcax", "cab", "bb", ""] {
+            assert_eq!(safe.apply(input.as_bytes()), dfa.apply(input.as_bytes()));
+        }
+        let haystack = b"xbcaxcaabab";
+        assert!(safe.find(haystack).eq(dfa.find(haystack)));
+        assert!(safe
+            .find_overlapping(haystack)
+            .eq(dfa.find_overlapping(haystack)));
+
+        let safe = std::sync::Arc::new(safe);
+        let handle = {
+            let safe = std::sync::Arc::clone(&safe);
+            std::thread::spawn(move || safe.find(haystack).count())
+        };
+        assert_eq!(handle.join().unwrap(), dfa.find(haystack).count());
+    }
+
+    #[test]
+    fn memory_usage_bytes() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let table_bytes = dfa.state_count() * 256 * mem::size_of::<StateNumber>();
+        let usage = dfa.memory_usage_bytes();
+        assert!(usage > table_bytes);
+        assert!(usage < 2 * table_bytes);
+        assert!(usage <= dfa.memory_estimate_bytes());
+    }
+
+    #[test]
+    fn dfa_builder() {
+        let mut builder = DfaBuilder::new();
+        let a = builder.new_state();
+        let ab = builder.new_state();
+        builder.add_transition(START, b'a', a).unwrap();
+        builder.add_transition(a, b'b', ab).unwrap();
+        builder.add_transition(a, b'b', ab).unwrap();
+        assert_eq!(
+            builder.add_transition(a, b'b', a),
+            Err(BuildError::DuplicateTransition { from: a, on: b'b' })
+        );
+        builder.mark_accepting(a, 0);
+        builder.mark_accepting(ab, 1);
+        builder.dict(vec![b"a".to_vec(), b"ab".to_vec()]);
+        let dfa = builder.build().unwrap();
+        assert_eq!(dfa.apply(b"a"), vec![0]);
+        assert_eq!(dfa.apply(b"ab"), vec![1]);
+        assert!(dfa.apply(b"b").is_empty());
+
+        let mut builder = DfaBuilder::new();
+        builder.mark_accepting(START, 3);
+        builder.dict(vec![b"".to_vec()]);
+        assert_eq!(builder.build(), Err(BuildError::InvalidPatternNumber));
+        let mut builder = DfaBuilder::new();
+        builder.mark_accepting(START, 0);
+        assert_eq!(builder.build(), Err(BuildError::InvalidPatternNumber));
+    }
+
+    #[test]
+    fn dfa_builder_round_trip() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let rebuilt = DfaBuilder::from_dfa(&dfa).build().unwrap();
+        assert_eq!(rebuilt, dfa);
+
+        // Pseudo-random inputs, most of which match nothing
+        let mut next = pseudo_random(7);
+        let inputs: Vec<Vec<u8>> = (0..200)
+            .map(|_| (0..next() % 7).map(|_| b"abcxyz"[next() % 6]).collect())
+            .collect();
+        let words = BASIC_DICTIONARY.iter().map(|word| word.as_bytes());
+        for input in words.chain(inputs.iter().map(|input| input.as_slice())) {
+            assert_eq!(rebuilt.apply(input), dfa.apply(input));
+        }
+
+        let mut builder = DfaBuilder::from_dfa(&dfa);
+        let extra = builder.new_state();
+        assert!(builder.add_transition(START, b'a', extra).is_err());
+        assert_eq!(
+            builder.build().unwrap().state_count(),
+            dfa.state_count() + 1
+        );
+    }
+
+    #[test]
+    fn language_equivalence() {
+        let dfa = |dict: &[&str]| {
+            NFA::from_dictionary(dict)
+                .powerset_construction()
+                .into_dfa()
+                .unwrap()
+        };
+        let mut reordered = BASIC_DICTIONARY.to_vec();
+        reordered.reverse();
+        let basic = dfa(BASIC_DICTIONARY);
+        assert!(are_equivalent(&basic, &dfa(&reordered)));
+        assert!(are_equivalent(&basic, &basic.minimize()));
+        assert!(!are_equivalent(&basic, &dfa(&["a", "ab", "bab"])));
+        assert!(!are_equivalent(&dfa(&["a"]), &dfa(&["b"])));
+
+        assert!(is_language_subset(&dfa(&["a"]), &dfa(&["a", "b"])));
+        assert!(!is_language_subset(&dfa(&["a", "b"]), &dfa(&["a"])));
+        assert!(is_language_subset(&basic, &dfa(&reordered)));
+        assert!(is_language_subset(&dfa(&[]), &basic));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn search_parallel() {
+        use crate::automaton::{search_parallel, search_parallel_count};
+
+        let haystacks: Vec<&[u8]> = HAYSTACK_SHERLOCK.as_bytes().chunks(1000).collect();
+        let nfa = NFA::from_dictionary(vec!["Sherlock", "Street"]);
+        let mut ignore_prefixes = NFA::from_dictionary(vec!["Sherlock", "Street"]);
+        ignore_prefixes.ignore_prefixes();
+        let dfa = ignore_prefixes.powerset_construction().into_dfa().unwrap();
+        let safe_ddfa = DfaBuilder::from_dfa(&dfa).build().unwrap().into_safe_ddfa();
+
+        let sequential: Vec<Vec<_>> = haystacks.iter().map(|h| dfa.find(h).collect()).collect();
+        let count = sequential.iter().map(Vec::len).sum::<usize>();
+        // Some of the 158 matches are cut in two by the chunks
+        assert!(count > 100 && count <= 158);
+        assert_eq!(search_parallel(&dfa, &haystacks), sequential);
+        assert_eq!(search_parallel(&safe_ddfa, &haystacks), sequential);
+        assert_eq!(search_parallel_count(&dfa, &haystacks), count);
+        let nfa_count: usize = haystacks.iter().map(|h| nfa.find(h).count()).sum();
+        assert_eq!(search_parallel_count(&nfa, &haystacks), nfa_count);
+        assert!(search_parallel(&dfa, &[] as &[&[u8]]).is_empty());
+    }
 }
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+
+use crate::automaton::{Automaton, Match};
+use crate::nfa::{Input, PatternNumber, StateNumber, NFA, START, STUCK};
+
+// A transition that hasn't been computed yet
+const UNKNOWN: StateNumber = StateNumber::MAX;
+
+struct LazyDFAState {
+    nfa_states: BTreeSet<StateNumber>,
+    transitions: [StateNumber; 256],
+    pattern_ends: Vec<PatternNumber>,
+}
+
+/// A DFA for an `NFA` that only builds the states a search actually visits, instead of all the
+///  states that `powerset_construction` builds up front. The states are cached behind locks,
+///  so the automaton can be used through a shared reference, also from several threads.
+pub struct LazyDFA<'a> {
+    nfa: &'a NFA,
+    // Sorted NFA states -> state number
+    state_map: RwLock<HashMap<Vec<StateNumber>, StateNumber>>,
+    states: RwLock<Vec<LazyDFAState>>,
+}
+
+impl<'a> LazyDFA<'a> {
+    pub fn new(nfa: &'a NFA) -> Self {
+        let lazy = LazyDFA {
+            nfa,
+            state_map: RwLock::new(HashMap::new()),
+            states: RwLock::new(Vec::new()),
+        };
+        {
+            let mut state_map = lazy.state_map.write().unwrap();
+            let mut states = lazy.states.write().unwrap();
+            // The stuck state loops on every byte, so it never needs to be computed
+            states.push(LazyDFAState {
+                nfa_states: BTreeSet::new(),
+                transitions: [STUCK; 256],
+                pattern_ends: Vec::new(),
+            });
+            state_map.insert(Vec::new(), STUCK);
+            state_map.insert(vec![STUCK], STUCK);
+            let start = nfa.start_state();
+            state_map.insert(start.iter().cloned().collect(), START);
+            states.push(LazyDFAState {
+                pattern_ends: nfa.patterns_of(&start),
+                nfa_states: start,
+                transitions: [UNKNOWN; 256],
+            });
+        }
+        lazy
+    }
+
+    /// The number of states built so far, including the start and stuck states
+    pub fn cached_state_count(&self) -> usize {
+        self.states.read().unwrap().len()
+    }
+
+    /// Builds the states that no search has needed yet, by following every byte in the alphabet
+    ///  from every state like `NFA::powerset_construction`, and returns the resulting
+    ///  deterministic `NFA`.
+    pub fn into_full_nfa(self) -> NFA {
+        let alphabet = self.nfa.alphabet().to_vec();
+        let mut worklist = vec![START];
+        let mut seen: BTreeSet<StateNumber> = worklist.iter().cloned().collect();
+        while let Some(state) = worklist.pop() {
+            for &byte in &alphabet {
+                let nxt = self.next_state(&state, &byte);
+                if nxt != STUCK && seen.insert(nxt) {
+                    worklist.push(nxt);
+                }
+            }
+        }
+
+        let states = self.states.into_inner().unwrap();
+        let parts = states
+            .into_iter()
+            .map(|state| {
+                let transitions: BTreeMap<Input, StateNumber> = alphabet
+                    .iter()
+                    .map(|&byte| (byte, state.transitions[byte as usize]))
+                    .filter(|&(_, to)| to != UNKNOWN)
+                    .collect();
+                (transitions, state.pattern_ends)
+            })
+            .collect();
+        self.nfa.deterministic_from_parts(parts)
+    }
+
+    // Adds the transition on `input` from `state`, and the target state if it's new
+    fn compute_transition(&self, state: StateNumber, input: Input) -> StateNumber {
+        let nfa_states = {
+            let states = self.states.read().unwrap();
+            if states[state].transitions[input as usize] != UNKNOWN {
+                return states[state].transitions[input as usize];
+            }
+            self.nfa.next_state(&states[state].nfa_states, &input)
+        };
+        let key: Vec<StateNumber> = nfa_states.iter().cloned().collect();
+
+        let mut state_map = self.state_map.write().unwrap();
+        let mut states = self.states.write().unwrap();
+        let nxt = match state_map.get(&key) {
+            Some(&nxt) => nxt,
+            None => {
+                let nxt = states.len();
+                states.push(LazyDFAState {
+                    pattern_ends: self.nfa.patterns_of(&nfa_states),
+                    nfa_states,
+                    transitions: [UNKNOWN; 256],
+                });
+                state_map.insert(key, nxt);
+                nxt
+            }
+        };
+        states[state].transitions[input as usize] = nxt;
+        nxt
+    }
+}
+
+impl<'a> Automaton<Input> for LazyDFA<'a> {
+    type State = StateNumber;
+    type Payload = PatternNumber;
+
+    fn start_state(&self) -> Self::State {
+        START
+    }
+
+    fn stuck_state(&self) -> Self::State {
+        STUCK
+    }
+
+    #[inline]
+    fn state_is_stuck(&self, &state: &Self::State) -> bool {
+        state == STUCK
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        self.nfa.max_match_len()
+    }
+
+    #[inline]
+    fn next_state(&self, &state: &Self::State, &input: &Input) -> Self::State {
+        self.compute_transition(state, input)
+    }
+
+    #[inline]
+    fn has_match(&self, &state: &Self::State, patt_no_offset: usize) -> bool {
+        patt_no_offset < self.states.read().unwrap()[state].pattern_ends.len()
+    }
+
+    #[inline]
+    fn get_match(&self, &state: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match {
+        let patt_no = self.states.read().unwrap()[state].pattern_ends[patt_no_offset];
+        Match {
+            payload: patt_no,
+            start: text_offset - self.nfa.match_len(patt_no),
+            end: text_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HAYSTACK_SHERLOCK: &str = include_str!("../benches/sherlock.txt");
+
+    #[test]
+    fn same_matches_as_powerset() {
+        let mut nfa = NFA::from_dictionary(["Sherlock", "Street", "Holmes", "Watson"]);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        let lazy = LazyDFA::new(&nfa);
+        let haystack = &HAYSTACK_SHERLOCK.as_bytes()[..20_000];
+        assert!(lazy.find(haystack).eq(dnfa.find(haystack)));
+        assert!(lazy
+            .find_overlapping(haystack)
+            .eq(dnfa.find_overlapping(haystack)));
+
+        let cached = lazy.cached_state_count();
+        let full = lazy.into_full_nfa();
+        assert!(cached <= full.reachable_states().len() + 1);
+        assert_eq!(full.reachable_states().len(), dnfa.reachable_states().len());
+        assert!(full.find(haystack).eq(dnfa.find(haystack)));
+    }
+
+    #[test]
+    fn builds_only_visited_states() {
+        let words: Vec<String> = (0..1000).map(|i| format!("word{}x", i)).collect();
+        let mut nfa = NFA::from_dictionary(&words);
+        nfa.ignore_prefixes();
+        let lazy = LazyDFA::new(&nfa);
+        assert_eq!(lazy.find(b"a word12x and word999x").count(), 2);
+        assert!(lazy.cached_state_count() < 50);
+        assert!(nfa.powerset_construction().reachable_states().len() > 1000);
+    }
+}
@@ -1,4 +1,11 @@
 pub mod automaton;
+#[cfg(feature = "unicode")]
+pub mod char_nfa;
 pub mod dfa;
+pub mod lazy_dfa;
 pub mod ndfa;
 pub mod nfa;
+pub mod scc;
+#[cfg(feature = "smallvec")]
+mod small_set;
+mod util;
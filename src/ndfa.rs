@@ -1,12 +1,13 @@
 use std::collections::btree_set::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::iter;
 
 use bit_vec::BitVec;
 
 use crate::dfa::{DFAState, DFA};
-use crate::nfa::{Input, StateNumber, START, STUCK};
+use crate::nfa::{Input, PatternNumber, StateNumber, START, STUCK};
 
 pub struct NDFA {
     // nfa_StateNumber -> (Input -> Set<nfa_StateNumber>; is_final)
@@ -19,6 +20,16 @@ pub struct NDFA {
     represents_nfa_states: Vec<HashSet<StateNumber>>,
     // reverse of represents_nfa_states
     nfa_states_to_dfa_state: HashMap<BTreeSet<StateNumber>, StateNumber>,
+    // nfa_StateNumber -> the patterns added with `add_word` that end there
+    pattern_ends: Vec<Vec<PatternNumber>>,
+    // the words added with `add_word`, by pattern number
+    dict: Vec<Vec<Input>>,
+}
+
+impl Default for NDFA {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NDFA {
@@ -38,7 +49,50 @@ impl NDFA {
                 [START].iter().cloned().collect(),
             ],
             nfa_states_to_dfa_state: nfa_to_dfa,
+            pattern_ends: vec![Vec::new(), Vec::new()],
+            dict: Vec::new(),
+        }
+    }
+
+    /// Builds the trie of the patterns with `add_word`, where pattern `i` ends in a state with
+    ///  `i` in its pattern ends, like `NFA::from_dictionary`.
+    pub fn from_dictionary<P, I>(dict: I) -> Self
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let mut ndfa = NDFA::new();
+        for word in dict {
+            ndfa.add_word(word.as_ref());
         }
+        ndfa.compact();
+        ndfa
+    }
+
+    /// Adds `word` as the next pattern, sharing the longest prefix that's already in the trie.
+    ///  The pattern number is the number of words added before it.
+    pub fn add_word(&mut self, word: &[u8]) {
+        let mut state = START;
+        let mut bytes = word.iter();
+        for &byte in &mut bytes {
+            match self.nfa_states[state]
+                .0
+                .get(&byte)
+                .and_then(|to| to.iter().next())
+            {
+                Some(&to) => state = to,
+                None => {
+                    state = self.new_state(state, byte);
+                    break;
+                }
+            }
+        }
+        for &byte in bytes {
+            state = self.new_state(state, byte);
+        }
+        self.pattern_ends[state].push(self.dict.len());
+        self.dict.push(word.to_vec());
+        self.mark_final(state);
     }
 
     pub fn start_state() -> StateNumber {
@@ -50,11 +104,13 @@ impl NDFA {
     }
 
     /// You can only add a new state by going from an existing state
+    #[allow(clippy::map_entry)]
     pub fn new_state(&mut self, from: StateNumber, on: Input) -> StateNumber {
         assert!(from < self.nfa_states.len());
 
         let new_state = self.nfa_states.len();
         self.nfa_states.push((HashMap::new(), false));
+        self.pattern_ends.push(Vec::new());
 
         let new_dfa_state = self.dfa_states.len();
         self.dfa_states.push((HashMap::new(), false));
@@ -92,18 +148,18 @@ impl NDFA {
                         .cloned()
                         .chain(iter::once(new_state))
                         .collect();
-                    let newer_dfa_state_option = self.nfa_states_to_dfa_state.get(&nfa_to_states);
-                    let new_dfa_to_state = if newer_dfa_state_option.is_none() {
-                        let newer_dfa_state = self.dfa_states.len();
-                        self.dfa_states
-                            .push(self.dfa_states[old_dfa_to_state].clone());
-                        self.represents_nfa_states
-                            .push(nfa_to_states.iter().cloned().collect());
-                        self.nfa_states_to_dfa_state
-                            .insert(nfa_to_states, newer_dfa_state);
-                        newer_dfa_state
-                    } else {
-                        *newer_dfa_state_option.unwrap()
+                    let new_dfa_to_state = match self.nfa_states_to_dfa_state.get(&nfa_to_states) {
+                        Some(&newer_dfa_state) => newer_dfa_state,
+                        None => {
+                            let newer_dfa_state = self.dfa_states.len();
+                            self.dfa_states
+                                .push(self.dfa_states[old_dfa_to_state].clone());
+                            self.represents_nfa_states
+                                .push(nfa_to_states.iter().cloned().collect());
+                            self.nfa_states_to_dfa_state
+                                .insert(nfa_to_states, newer_dfa_state);
+                            newer_dfa_state
+                        }
                     };
                     self.dfa_states[from_dfa].0.insert(on, new_dfa_to_state);
                 }
@@ -125,6 +181,7 @@ impl NDFA {
                 return self;
             }
         }
+        from_state.entry(on).or_default().insert(to);
 
         for &from_dfa in &self.corresponding_dfa_states[from] {
             let from_dfa_state = &mut self.dfa_states[from_dfa].0;
@@ -140,18 +197,18 @@ impl NDFA {
                             .collect()
                     });
             nfa_to_states.insert(to);
-            let newer_dfa_state_option = self.nfa_states_to_dfa_state.get(&nfa_to_states);
-            let new_dfa_to_state = if newer_dfa_state_option.is_none() {
-                let newer_dfa_state = self.dfa_states.len();
-                self.dfa_states
-                    .push(self.dfa_states[old_dfa_to_state].clone());
-                self.represents_nfa_states
-                    .push(nfa_to_states.iter().cloned().collect());
-                self.nfa_states_to_dfa_state
-                    .insert(nfa_to_states, newer_dfa_state);
-                newer_dfa_state
-            } else {
-                *newer_dfa_state_option.unwrap()
+            let new_dfa_to_state = match self.nfa_states_to_dfa_state.get(&nfa_to_states) {
+                Some(&newer_dfa_state) => newer_dfa_state,
+                None => {
+                    let newer_dfa_state = self.dfa_states.len();
+                    self.dfa_states
+                        .push(self.dfa_states[old_dfa_to_state].clone());
+                    self.represents_nfa_states
+                        .push(nfa_to_states.iter().cloned().collect());
+                    self.nfa_states_to_dfa_state
+                        .insert(nfa_to_states, newer_dfa_state);
+                    newer_dfa_state
+                }
             };
             self.dfa_states[from_dfa].0.insert(on, new_dfa_to_state);
         }
@@ -168,6 +225,45 @@ impl NDFA {
         self
     }
 
+    /// The NFA states in topological order, computed with Kahn's algorithm, or `None` if the NFA
+    ///  has a cycle. The stuck state is left out, so for an acyclic NFA this starts with the start
+    ///  state.
+    pub fn topological_order(&self) -> Option<Vec<StateNumber>> {
+        let mut in_degree = vec![0; self.nfa_states.len()];
+        for (transitions, _) in &self.nfa_states {
+            for &to in transitions.values().flatten() {
+                in_degree[to] += 1;
+            }
+        }
+
+        let mut worklist: VecDeque<StateNumber> = (0..self.nfa_states.len())
+            .filter(|&state| state != STUCK && in_degree[state] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.nfa_states.len() - 1);
+        while let Some(state) = worklist.pop_front() {
+            order.push(state);
+            let mut successors: Vec<StateNumber> = self.nfa_states[state]
+                .0
+                .values()
+                .flatten()
+                .cloned()
+                .collect();
+            successors.sort_unstable();
+            for to in successors {
+                in_degree[to] -= 1;
+                if in_degree[to] == 0 {
+                    worklist.push_back(to);
+                }
+            }
+        }
+
+        if order.len() == self.nfa_states.len() - 1 {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
     /// You can finalize the ndfa into a dfa, basically forgetting the nfa part you used to build it
     pub fn finalize(&self) -> DFA {
         let mut finals = BitVec::with_capacity(self.dfa_states.len());
@@ -175,35 +271,40 @@ impl NDFA {
         let states: Vec<_> = self
             .dfa_states
             .iter()
-            .map(|state| {
+            .zip(&self.represents_nfa_states)
+            .map(|(state, nfa_states)| {
+                let pattern_ends: BTreeSet<PatternNumber> = nfa_states
+                    .iter()
+                    .flat_map(|&nfa_state| self.pattern_ends[nfa_state].iter().cloned())
+                    .collect();
                 DFAState::new(
                     {
-                        let mut transitions = vec![STUCK; 256];
+                        let mut transitions = [STUCK; 256];
                         finals.push(state.1);
                         for (&input, &to) in &state.0 {
                             transitions[input as usize] = to;
                         }
-                        transitions.into_boxed_slice()
+                        transitions
                     },
-                    vec![],
+                    pattern_ends.into_iter().collect(),
                 )
             })
             .collect();
 
-        DFA::new(states.into_boxed_slice(), finals, vec![])
+        DFA::new(states.into_boxed_slice(), finals, self.dict.clone())
     }
 
     /// Remove unused DFA states created during the build
     pub fn compact(&mut self) -> &mut Self {
-        let mut seen_states = HashSet::new();
-        seen_states.insert(START);
+        let mut seen_states = BitVec::from_elem(self.dfa_states.len(), false);
+        seen_states.set(START, true);
 
         let mut worklist = vec![START];
         while let Some(state) = worklist.pop() {
             let from = &self.dfa_states[state];
             for &to in from.0.values() {
-                if !seen_states.contains(&to) {
-                    seen_states.insert(to);
+                if !seen_states[to] {
+                    seen_states.set(to, true);
                     worklist.push(to);
                 }
             }
@@ -211,9 +312,13 @@ impl NDFA {
 
         let mut dropped = 0;
         let mut renumber = HashMap::new();
-        for idx in 1..seen_states.len() {
-            if !seen_states.contains(&idx) {
+        for idx in 1..self.dfa_states.len() {
+            if !seen_states[idx] {
                 dropped += 1;
+                let nfa_states = self.represents_nfa_states[idx].iter().cloned().collect();
+                if self.nfa_states_to_dfa_state.get(&nfa_states) == Some(&idx) {
+                    self.nfa_states_to_dfa_state.remove(&nfa_states);
+                }
             } else if dropped > 0 {
                 renumber.insert(idx, idx - dropped);
                 self.dfa_states.swap(idx - dropped, idx);
@@ -246,3 +351,62 @@ impl NDFA {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfa::NFA;
+
+    #[test]
+    fn topological_order() {
+        let mut ndfa = NDFA::new();
+        let a = ndfa.new_state(START, b'a');
+        let ab = ndfa.new_state(a, b'b');
+        let b = ndfa.new_state(START, b'b');
+        let bc = ndfa.new_state(b, b'c');
+        ndfa.new_edge(a, bc, b'b');
+
+        let order = ndfa.topological_order().unwrap();
+        assert_eq!(order.len(), 5);
+        assert_eq!(order[0], START);
+        let position = |state| order.iter().position(|&s| s == state).unwrap();
+        assert!(position(a) < position(ab));
+        assert!(position(a) < position(bc));
+        assert!(position(b) < position(bc));
+        assert!(ndfa.nfa_states[*order.last().unwrap()].0.is_empty());
+
+        ndfa.new_edge(START, START, b'a');
+        assert_eq!(ndfa.topological_order(), None);
+    }
+
+    static BASIC_DICTIONARY: &[&str] = &["a", "ab", "bab", "bc", "bca", "c", "caa"];
+
+    #[test]
+    fn from_dictionary() {
+        let ndfa = NDFA::from_dictionary(BASIC_DICTIONARY);
+        let dfa = ndfa.finalize();
+        for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
+            assert_eq!(dfa.apply(word.as_bytes()), vec![patt_no]);
+        }
+        for word in &["", "b", "ba", "bb", "abc", "caaa"] {
+            assert!(dfa.apply(word.as_bytes()).is_empty());
+        }
+        let nfa_dfa = NFA::from_dictionary(BASIC_DICTIONARY)
+            .powerset_construction()
+            .into_dfa()
+            .unwrap();
+        assert_eq!(dfa.state_count(), nfa_dfa.state_count());
+    }
+
+    #[test]
+    fn add_word() {
+        let mut ndfa = NDFA::from_dictionary(["ab", "b"]);
+        ndfa.add_word(b"abc");
+        ndfa.add_word(b"b");
+        let dfa = ndfa.finalize();
+        assert_eq!(dfa.apply(b"abc"), vec![2]);
+        assert_eq!(dfa.apply(b"ab"), vec![0]);
+        assert_eq!(dfa.apply(b"b"), vec![1, 3]);
+        assert_eq!(dfa.state_count(), 6);
+    }
+}
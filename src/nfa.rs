@@ -1,11 +1,16 @@
 use bit_vec::BitVec;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt;
+use std::iter;
+use std::sync::OnceLock;
 
 use crate::automaton::{Automaton, Match};
 use crate::dfa::{DFAState, DFA};
+use crate::util::{dot_header, dot_pattern_ends, explode_ranges, implode_ranges};
 
 pub const START: usize = 1;
 pub const STUCK: usize = 0;
@@ -15,9 +20,22 @@ pub type StateNumber = usize;
 pub type PatternNumber = usize;
 pub type Depth = usize;
 
+#[cfg(not(feature = "smallvec"))]
+type StateSet = BTreeSet<StateNumber>;
+#[cfg(feature = "smallvec")]
+type StateSet = crate::small_set::SmallStateSet;
+
+// The maps from sets of NFA states to DNFA states in the powerset construction
+#[cfg(not(feature = "fast-hash"))]
+type StatesMap<K> = HashMap<K, StateNumber>;
+#[cfg(feature = "fast-hash")]
+type StatesMap<K> = rustc_hash::FxHashMap<K, StateNumber>;
+
 #[derive(Clone, Default)]
 struct NFAState {
-    transitions: BTreeMap<Input, BTreeSet<StateNumber>>,
+    transitions: BTreeMap<Input, StateSet>,
+    // Transitions that don't consume input
+    epsilon: BTreeSet<StateNumber>,
     pattern_ends: Vec<PatternNumber>,
 }
 
@@ -27,6 +45,90 @@ pub struct NFA {
     states: Vec<NFAState>,
     dict: Vec<Vec<Input>>,
     depth_map: BTreeMap<Depth, BTreeSet<StateNumber>>,
+    failure_links: Option<Vec<StateNumber>>,
+    state_names: Vec<Option<String>>,
+    // pattern id -> index in dict, when the pattern ids aren't the dict indices
+    dict_index: Option<HashMap<PatternNumber, usize>>,
+    // canonical byte per byte, for `from_dictionary_case_fold`
+    fold_table: Option<[Input; 256]>,
+    // for `from_dictionary_nocase`: the transitions on lowercase ASCII letters have uppercase twins
+    nocase: bool,
+    // priority per pattern number, for `from_prioritized_dictionary`
+    priorities: Vec<u32>,
+}
+
+/// Options for `NFA::from_dictionary_with_config`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NfaConfig {
+    /// Match ASCII letters in either case, see `NFA::from_dictionary_nocase`
+    pub case_insensitive: bool,
+}
+
+/// Errors from the checked dictionary constructors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DictError {
+    /// The validator passed to `from_dictionary_validated` rejected pattern `pattern_no`
+    CustomValidationFailed {
+        pattern_no: PatternNumber,
+        message: String,
+    },
+    /// Pattern `pattern_no` is longer than the maximum given to `from_dictionary_with_limit`
+    PatternTooLong {
+        pattern_no: PatternNumber,
+        length: usize,
+        max: usize,
+    },
+    /// `from_dictionary_with_ids` got fewer ids (or `from_dictionary_with_payloads` fewer
+    ///  payloads) than patterns
+    IdsTooShort { ids: usize, patterns: usize },
+    /// `from_dictionary_with_ids` got the same id for patterns `first` and `second`
+    DuplicateId {
+        id: PatternNumber,
+        first: PatternNumber,
+        second: PatternNumber,
+    },
+    /// The automaton built by `from_dictionary_safe` doesn't accept these patterns
+    PatternsNotAccepted { pattern_nos: Vec<PatternNumber> },
+}
+
+/// Errors from converting an `NFA` into a `DFA` or the goto table, from the transformations
+///  that need a trie, and from combining NFAs with `from_alternation`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConversionError {
+    /// Some state has more than one target state for the same byte
+    NotDeterministic,
+    /// The automaton is no longer a trie, e.g. after `ignore_prefixes`
+    NotATrie,
+    /// Part `part` of `from_alternation` folds cases differently from the first part
+    DifferentCaseFolding { part: usize },
+}
+
+/// Errors from `NFA::from_dot`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DotParseError {
+    /// The text ends before the graph does
+    UnexpectedEof,
+    /// A line, or the label in it, isn't one that `NFA::dot` writes
+    InvalidLabel(String),
+    /// The label of an edge isn't a list of byte ranges
+    InvalidRangeSyntax(String),
+    /// The same edge is given twice
+    DuplicateEdge {
+        from: StateNumber,
+        to: StateNumber,
+        byte: Input,
+    },
+}
+
+/// Which of the accepted patterns `NFA::apply_mode` returns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Only the first accepted pattern
+    First,
+    /// All accepted patterns, like `apply`
+    All,
+    /// The accepted patterns of maximal length
+    Longest,
 }
 
 impl NFA {
@@ -36,23 +138,28 @@ impl NFA {
             states: Vec::new(),
             dict: Vec::new(),
             depth_map: BTreeMap::new(),
+            failure_links: None,
+            state_names: Vec::new(),
+            dict_index: None,
+            fold_table: None,
+            nocase: false,
+            priorities: Vec::new(),
         }
     }
 
+    /// Builds a trie of the patterns, where pattern `i` ends in a state with `i` in its pattern
+    ///  ends. Patterns are raw bytes, so any byte value (including `\0` and bytes from 0x80)
+    ///  can be used, e.g. for matching binary data like network packets.
     pub fn from_dictionary<P, I>(dict: I) -> Self
     where
         P: AsRef<[u8]>,
-        I: IntoIterator<Item = P> + Clone,
+        I: IntoIterator<Item = P>,
     {
         let mut nfa = NFA {
             alphabet: Vec::new(),
             states: Vec::new(),
-            dict: dict
-                .clone()
-                .into_iter()
-                .map(|p| p.as_ref().to_vec())
-                .collect(),
-            depth_map: BTreeMap::new(),
+            dict: dict.into_iter().map(|p| p.as_ref().to_vec()).collect(),
+            ..NFA::new()
         };
         // the start and stuck states
         nfa.states.push(NFAState::new());
@@ -60,38 +167,523 @@ impl NFA {
 
         // collect the alphabet from the patterns while we're looping through them anyway
         let mut alphabet = BTreeSet::new();
-        for (pattern_no, bytes) in dict.into_iter().enumerate() {
-            let mut cur_state = START;
-            for &byte in bytes.as_ref() {
-                alphabet.insert(byte);
-                // If there is a transition on this byte from the cur_state
-                //  just go there. (We can be sure there will be only one at this point)
-                if let Some(&state) = nfa.states[cur_state]
-                    .transitions
-                    .get(&byte)
-                    .map_or(None, |x| x.iter().next())
-                {
-                    cur_state = state;
+        // The states along the path of the previous pattern. A pattern only needs to walk the
+        //  trie from where it stops sharing a prefix with the previous one, which saves most of
+        //  the walking for a sorted dictionary.
+        let mut prev_path = vec![START];
+        let mut prev_bytes: &[Input] = &[];
+        for (pattern_no, bytes) in nfa.dict.iter().enumerate() {
+            alphabet.extend(bytes);
+            let shared = prev_bytes
+                .iter()
+                .zip(bytes)
+                .take_while(|(b1, b2)| b1 == b2)
+                .count();
+            prev_path.truncate(shared + 1);
+            for byte in &bytes[shared..] {
+                let from = prev_path[prev_path.len() - 1];
+                prev_path.push(insert_trie_path(
+                    &mut nfa.states,
+                    from,
+                    std::slice::from_ref(byte),
+                ));
+            }
+            nfa.states[prev_path[bytes.len()]]
+                .pattern_ends
+                .push(pattern_no);
+            prev_bytes = bytes;
+        }
+
+        nfa.alphabet = alphabet.into_iter().collect();
+        nfa
+    }
+
+    /// Builds the same automaton as `from_dictionary` (up to state numbering), using all cores.
+    /// Patterns that start with different bytes never share a state other than the start state,
+    ///  so the tries per first byte are built in parallel, with rayon. They are then added to the
+    ///  automaton one by one.
+    #[cfg(feature = "rayon")]
+    pub fn from_dictionary_parallel<P, I>(dict: I) -> Self
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        use rayon::prelude::*;
+
+        let mut nfa = NFA::new();
+        nfa.dict = dict.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        nfa.states.push(NFAState::new());
+        nfa.states.push(NFAState::new());
+
+        let mut buckets: Vec<Vec<PatternNumber>> = vec![Vec::new(); 256];
+        for (pattern_no, bytes) in nfa.dict.iter().enumerate() {
+            match bytes.first() {
+                Some(&byte) => buckets[byte as usize].push(pattern_no),
+                None => nfa.states[START].pattern_ends.push(pattern_no),
+            }
+        }
+        let buckets: Vec<(Input, Vec<PatternNumber>)> = buckets
+            .into_iter()
+            .enumerate()
+            .filter(|(_, patterns)| !patterns.is_empty())
+            .map(|(byte, patterns)| (byte as Input, patterns))
+            .collect();
+
+        // Every sub-trie has its own numbering, with 0 for the state after the first byte
+        let dict = &nfa.dict;
+        let sub_tries: Vec<(Input, Vec<NFAState>)> = buckets
+            .par_iter()
+            .map(|(byte, patterns)| {
+                let mut states = vec![NFAState::new()];
+                for &pattern_no in patterns {
+                    let end_state = insert_trie_path(&mut states, 0, &dict[pattern_no][1..]);
+                    states[end_state].pattern_ends.push(pattern_no);
                 }
-                // Otherwise add a new transition, and add the corresponding state
-                else {
-                    let nxt_state = nfa.states.len();
-                    nfa.states.push(NFAState::new());
-                    nfa.states[cur_state]
-                        .transitions
-                        .entry(byte)
-                        .or_insert_with(BTreeSet::new)
-                        .insert(nxt_state);
-                    cur_state = nxt_state;
+                (*byte, states)
+            })
+            .collect();
+
+        let mut alphabet = BTreeSet::new();
+        for (byte, states) in sub_tries {
+            let offset = nfa.states.len();
+            nfa.states[START]
+                .transitions
+                .entry(byte)
+                .or_default()
+                .insert(offset);
+            for mut state in states {
+                alphabet.extend(state.transitions.keys());
+                for to in state.transitions.values_mut() {
+                    *to = to.iter().map(|&s| s + offset).collect();
                 }
+                nfa.states.push(state);
             }
-            nfa.states[cur_state].pattern_ends.push(pattern_no);
         }
 
+        alphabet.extend(nfa.states[START].transitions.keys());
         nfa.alphabet = alphabet.into_iter().collect();
         nfa
     }
 
+    /// Like `from_dictionary`, but every byte `b` is treated as `fold[b]`, both in the patterns
+    ///  and in the input. E.g. with `fold[b] = b.to_ascii_lowercase()` matching is ASCII case
+    ///  insensitive. Unlike adding transitions for the other bytes, this doesn't make the
+    ///  automaton any bigger. `into_dfa` bakes the table into the transitions of the `DFA`.
+    pub fn from_dictionary_case_fold<P, I>(dict: I, fold: &[u8; 256]) -> Self
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let dict: Vec<Vec<Input>> = dict.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        let folded = dict.iter().map(|bytes| {
+            bytes
+                .iter()
+                .map(|&byte| fold[byte as usize])
+                .collect::<Vec<_>>()
+        });
+        let mut nfa = NFA::from_dictionary(folded);
+        nfa.dict = dict;
+        nfa.fold_table = Some(*fold);
+        nfa
+    }
+
+    /// Like `from_dictionary`, but ASCII letters match in either case. Patterns are inserted
+    ///  in lowercase, and every transition on a lowercase letter gets a twin on the uppercase
+    ///  letter to the same states. Other bytes, including all non-ASCII bytes, are unaffected.
+    /// Unlike `from_dictionary_case_fold`, this doesn't need a lookup per input byte, at the
+    ///  cost of the extra transitions.
+    pub fn from_dictionary_nocase<P, I>(dict: I) -> Self
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let dict: Vec<Vec<Input>> = dict.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        let mut nfa = NFA::from_dictionary(dict.iter().map(|bytes| bytes.to_ascii_lowercase()));
+        nfa.dict = dict;
+        nfa.add_uppercase_twins();
+        nfa
+    }
+
+    // Gives every transition on a lowercase ASCII letter a twin on the uppercase letter, for
+    //  `from_dictionary_nocase`
+    fn add_uppercase_twins(&mut self) {
+        self.nocase = true;
+        let mut alphabet: BTreeSet<Input> = self.alphabet.iter().cloned().collect();
+        for state in &mut self.states {
+            let upper: Vec<(Input, StateSet)> = state
+                .transitions
+                .range(b'a'..=b'z')
+                .map(|(&byte, to)| (byte ^ 0x20, to.clone()))
+                .collect();
+            for (byte, to) in upper {
+                alphabet.insert(byte);
+                state.transitions.insert(byte, to);
+            }
+        }
+        self.alphabet = alphabet.into_iter().collect();
+    }
+
+    // The bytes that `pattern` is inserted into the trie with: folded by the fold table, or in
+    //  lowercase for `from_dictionary_nocase`
+    fn trie_bytes(&self, pattern: &[Input]) -> Vec<Input> {
+        if self.nocase {
+            pattern.to_ascii_lowercase()
+        } else {
+            pattern.iter().map(|&byte| self.fold(byte)).collect()
+        }
+    }
+
+    /// `from_dictionary` or one of its variants, as selected by `config`
+    pub fn from_dictionary_with_config<P, I>(dict: I, config: NfaConfig) -> Self
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        if config.case_insensitive {
+            NFA::from_dictionary_nocase(dict)
+        } else {
+            NFA::from_dictionary(dict)
+        }
+    }
+
+    /// Like `from_dictionary`, but checks that the automaton accepts every pattern before
+    ///  returning it. `from_dictionary` used to need `dict` to be `Clone` and iterated over it
+    ///  twice, so a clone that yields other patterns gave a broken automaton. It now collects
+    ///  `dict` once and builds from that, which is as fast, so there is no separate `Clone`-based
+    ///  `unsafe_from_dictionary`, and this check can only catch a bug in the construction.
+    pub fn from_dictionary_safe<P, I>(dict: I) -> Result<Self, DictError>
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let nfa = NFA::from_dictionary(dict);
+        nfa.verify_dict_completeness()
+            .map_err(|pattern_nos| DictError::PatternsNotAccepted { pattern_nos })?;
+        Ok(nfa)
+    }
+
+    /// Like `from_dictionary`, but first calls `validator` with the number and bytes of every
+    ///  pattern. The first pattern it rejects is reported in the error.
+    pub fn from_dictionary_validated<P, I, F>(dict: I, validator: F) -> Result<Self, DictError>
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+        F: Fn(usize, &[u8]) -> Result<(), String>,
+    {
+        let dict: Vec<Vec<Input>> = dict.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        for (pattern_no, bytes) in dict.iter().enumerate() {
+            validator(pattern_no, bytes).map_err(|message| DictError::CustomValidationFailed {
+                pattern_no,
+                message,
+            })?;
+        }
+        Ok(NFA::from_dictionary(&dict))
+    }
+
+    /// Like `from_dictionary`, but refuses patterns longer than `max_len` bytes, for when the
+    ///  dictionary comes from an untrusted source.
+    pub fn from_dictionary_with_limit<P, I>(dict: I, max_len: usize) -> Result<Self, DictError>
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let dict: Vec<Vec<Input>> = dict.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        if let Some((pattern_no, bytes)) = dict.iter().enumerate().find(|(_, p)| p.len() > max_len)
+        {
+            return Err(DictError::PatternTooLong {
+                pattern_no,
+                length: bytes.len(),
+                max: max_len,
+            });
+        }
+        Ok(NFA::from_dictionary(&dict))
+    }
+
+    /// Like `from_dictionary`, but pattern `i` is reported as `ids[i]` instead of `i`, for when
+    ///  the patterns are numbered by something outside of this automaton. The ids must be
+    ///  distinct, and any ids beyond the number of patterns are ignored.
+    pub fn from_dictionary_with_ids<P, I>(
+        dict: I,
+        ids: Vec<PatternNumber>,
+    ) -> Result<Self, DictError>
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let mut nfa = NFA::from_dictionary(dict);
+        if ids.len() < nfa.dict.len() {
+            return Err(DictError::IdsTooShort {
+                ids: ids.len(),
+                patterns: nfa.dict.len(),
+            });
+        }
+        let mut dict_index = HashMap::new();
+        for (second, &id) in ids.iter().take(nfa.dict.len()).enumerate() {
+            if let Some(first) = dict_index.insert(id, second) {
+                return Err(DictError::DuplicateId { id, first, second });
+            }
+        }
+        for state in &mut nfa.states {
+            for patt_no in &mut state.pattern_ends {
+                *patt_no = ids[*patt_no];
+            }
+        }
+        nfa.dict_index = Some(dict_index);
+        Ok(nfa)
+    }
+
+    /// Like `from_dictionary`, for patterns that each come with a label. The labels are returned
+    ///  by pattern number, see `label_of`.
+    pub fn from_labeled_dictionary<P: AsRef<[u8]>>(entries: &[(P, &str)]) -> (NFA, Vec<String>) {
+        let nfa = NFA::from_dictionary(entries.iter().map(|(pattern, _)| pattern));
+        let labels = entries.iter().map(|(_, label)| label.to_string()).collect();
+        (nfa, labels)
+    }
+
+    /// The label of the pattern of `m`, from the labels of `from_labeled_dictionary`
+    pub fn label_of<'a>(labels: &'a [String], m: &Match) -> &'a str {
+        &labels[m.payload]
+    }
+
+    /// Like `from_dictionary`, for patterns that each come with a priority. Where several
+    ///  patterns end at the same position, `find_priority` only reports the one with the
+    ///  highest priority.
+    pub fn from_prioritized_dictionary<P: AsRef<[u8]>>(entries: &[(P, u32)]) -> NFA {
+        let mut nfa = NFA::from_dictionary(entries.iter().map(|(pattern, _)| pattern));
+        nfa.priorities = entries.iter().map(|&(_, priority)| priority).collect();
+        nfa
+    }
+
+    /// The priority of pattern `patt_no`, which is 0 for a dictionary without priorities
+    pub fn priority_of(&self, patt_no: PatternNumber) -> u32 {
+        self.priorities.get(patt_no).cloned().unwrap_or(0)
+    }
+
+    /// Like `from_dictionary`, but matches of pattern `i` report `payloads[i]` instead of `i`,
+    ///  e.g. a category or a priority. Any payloads beyond the number of patterns are ignored.
+    pub fn from_dictionary_with_payloads<P, I, T>(
+        dict: I,
+        payloads: Vec<T>,
+    ) -> Result<NfaWithPayload<T>, DictError>
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        let nfa = NFA::from_dictionary(dict);
+        if payloads.len() < nfa.dict.len() {
+            return Err(DictError::IdsTooShort {
+                ids: payloads.len(),
+                patterns: nfa.dict.len(),
+            });
+        }
+        Ok(NfaWithPayload { nfa, payloads })
+    }
+
+    /// The union of the `parts`, with their states renumbered after each other. The start
+    ///  states of the parts are merged into one where that's safe: when nothing leads back to
+    ///  the start state of a part, it's only used at the start of the input. A part whose start
+    ///  state can be reached again, e.g. after `ignore_prefixes`, keeps its own entry state
+    ///  instead, with an epsilon transition to it from the new start state, so its loops don't
+    ///  leak into the other parts.
+    /// The dictionary is the concatenation of the dictionaries of the parts, and the patterns
+    ///  are numbered by their position in it, also for parts with pattern ids, and keep their
+    ///  priorities. Failure links, depth maps and state names aren't kept. All parts should use
+    ///  the same case folding, if any.
+    pub fn from_alternation(parts: Vec<NFA>) -> Result<NFA, ConversionError> {
+        let mut nfa = NFA {
+            fold_table: parts.first().and_then(|part| part.fold_table),
+            nocase: parts.first().is_some_and(|part| part.nocase),
+            ..NFA::new()
+        };
+        nfa.states.push(NFAState::new());
+        nfa.states.push(NFAState::new());
+
+        let mut alphabet = BTreeSet::new();
+        for (part_no, part) in parts.into_iter().enumerate() {
+            if part.fold_table != nfa.fold_table || part.nocase != nfa.nocase {
+                return Err(ConversionError::DifferentCaseFolding { part: part_no });
+            }
+            alphabet.extend(part.alphabet);
+            if part.states.is_empty() {
+                continue;
+            }
+            let start_reentered = part.states.iter().any(|state| {
+                state.transitions.values().any(|to| to.contains(&START))
+                    || state.epsilon.contains(&START)
+            });
+            let entry = if start_reentered {
+                nfa.states.push(NFAState::new());
+                let entry = nfa.states.len() - 1;
+                nfa.states[START].epsilon.insert(entry);
+                entry
+            } else {
+                START
+            };
+            let offset = nfa.states.len();
+            let renumber = |state: StateNumber| match state {
+                STUCK => STUCK,
+                START => entry,
+                _ => state - 2 + offset,
+            };
+            let dict_offset = nfa.dict.len();
+            let dict_index = part.dict_index;
+            let renumber_pattern = |patt_no: PatternNumber| match &dict_index {
+                Some(dict_index) => dict_offset + dict_index[&patt_no],
+                None => dict_offset + patt_no,
+            };
+            if !part.priorities.is_empty() {
+                // The priorities are by pattern number, which is the position in the dictionary
+                //  once the parts are combined
+                nfa.priorities.resize(dict_offset + part.dict.len(), 0);
+                match &dict_index {
+                    Some(dict_index) => {
+                        for (&id, &position) in dict_index {
+                            nfa.priorities[dict_offset + position] =
+                                part.priorities.get(id).cloned().unwrap_or(0);
+                        }
+                    }
+                    None => {
+                        for (patt_no, &priority) in part.priorities.iter().enumerate() {
+                            nfa.priorities[dict_offset + patt_no] = priority;
+                        }
+                    }
+                }
+            }
+
+            for (number, state) in part.states.into_iter().enumerate() {
+                let transitions = state.transitions.into_iter().map(|(input, to)| {
+                    let to: StateSet = to.into_iter().map(renumber).collect();
+                    (input, to)
+                });
+                let epsilon = state.epsilon.into_iter().map(renumber);
+                let pattern_ends = state.pattern_ends.into_iter().map(renumber_pattern);
+                if number == STUCK || number == START {
+                    let merged = &mut nfa.states[renumber(number)];
+                    for (input, to) in transitions {
+                        let targets = merged.transitions.entry(input).or_default();
+                        for state in to {
+                            targets.insert(state);
+                        }
+                    }
+                    merged.epsilon.extend(epsilon);
+                    merged.pattern_ends.extend(pattern_ends);
+                } else {
+                    nfa.states.push(NFAState {
+                        transitions: transitions.collect(),
+                        epsilon: epsilon.collect(),
+                        pattern_ends: pattern_ends.collect(),
+                    });
+                }
+            }
+            nfa.dict.extend(part.dict);
+        }
+        nfa.alphabet = alphabet.into_iter().collect();
+        Ok(nfa)
+    }
+
+    /// The automaton of the reversed patterns, with the same pattern numbers and alphabet. After
+    ///  a match that ends at `end`, running this one backwards from `end` finds where the match
+    ///  starts. The patterns are folded like those of `from_dictionary_case_fold` or
+    ///  `from_dictionary_nocase` if this automaton was built with one of those.
+    pub fn reversed(&self) -> NFA {
+        let reversed_dict: Vec<Vec<Input>> = self
+            .dict
+            .iter()
+            .map(|bytes| {
+                let mut bytes = bytes.clone();
+                bytes.reverse();
+                bytes
+            })
+            .collect();
+        let dict = reversed_dict.iter().map(|bytes| self.trie_bytes(bytes));
+        let mut reversed = match &self.dict_index {
+            None => NFA::from_dictionary(dict),
+            Some(dict_index) => {
+                let mut ids = vec![0; self.dict.len()];
+                for (&id, &idx) in dict_index {
+                    ids[idx] = id;
+                }
+                NFA::from_dictionary_with_ids(dict, ids).expect("There is an id per pattern")
+            }
+        };
+        reversed.dict = reversed_dict;
+        reversed.fold_table = self.fold_table;
+        if self.nocase {
+            reversed.add_uppercase_twins();
+        }
+        reversed.alphabet = self.alphabet.clone();
+        reversed.priorities = self.priorities.clone();
+        reversed
+    }
+
+    /// Adds `pattern` to the trie like `from_dictionary` does, sharing the states of the longest
+    ///  prefix that's already there, and returns its pattern number. The pattern is folded like
+    ///  the others for `from_dictionary_case_fold` and `from_dictionary_nocase`. Failure links
+    ///  are recomputed if there are any, and the depth map is dropped.
+    /// Fails if the NFA is no longer a trie (see `is_trie`), e.g. after `ignore_prefixes`. The
+    ///  uppercase twins of `from_dictionary_nocase` are fine.
+    pub fn add_pattern(&mut self, pattern: &[u8]) -> Result<PatternNumber, ConversionError> {
+        if self.states.is_empty() {
+            self.states.push(NFAState::new());
+            self.states.push(NFAState::new());
+        }
+        if !self.is_trie_with_twins(self.nocase) {
+            return Err(ConversionError::NotATrie);
+        }
+        let bytes = self.trie_bytes(pattern);
+        for &byte in &bytes {
+            let twin = (self.nocase && byte.is_ascii_lowercase()).then_some(byte ^ 0x20);
+            for byte in iter::once(byte).chain(twin) {
+                if let Err(idx) = self.alphabet.binary_search(&byte) {
+                    self.alphabet.insert(idx, byte);
+                }
+            }
+        }
+        let end_state = insert_trie_path(&mut self.states, START, &bytes);
+        if self.nocase {
+            let mut state = START;
+            for &byte in &bytes {
+                let to = self.states[state].transitions[&byte].clone();
+                if byte.is_ascii_lowercase() {
+                    self.states[state]
+                        .transitions
+                        .insert(byte ^ 0x20, to.clone());
+                }
+                state = *to.iter().next().expect("The path was just inserted");
+            }
+        }
+
+        let patt_no = match &mut self.dict_index {
+            Some(dict_index) => {
+                let patt_no = dict_index.keys().max().map_or(0, |&max| max + 1);
+                dict_index.insert(patt_no, self.dict.len());
+                patt_no
+            }
+            None => self.dict.len(),
+        };
+        self.states[end_state].pattern_ends.push(patt_no);
+        self.dict.push(pattern.to_vec());
+
+        self.depth_map = BTreeMap::new();
+        if self.failure_links.is_some() {
+            self.build_failure_links();
+        }
+        debug_assert!(self.verify_dict_completeness().is_ok());
+        Ok(patt_no)
+    }
+
+    /// `add_pattern` for every pattern
+    pub fn add_patterns<P, I>(&mut self, patterns: I) -> Result<(), ConversionError>
+    where
+        P: AsRef<[u8]>,
+        I: IntoIterator<Item = P>,
+    {
+        for pattern in patterns {
+            self.add_pattern(pattern.as_ref())?;
+        }
+        Ok(())
+    }
+
     pub fn add_depth_map(&mut self) {
         if !self.depth_map.is_empty() {
             return;
@@ -100,7 +692,7 @@ impl NFA {
 
         let mut depth = 0;
 
-        let mut visited = vec![false; states_len];
+        let mut visited = BitVec::from_elem(states_len, false);
         let mut cur_nodes = BTreeSet::new();
         let mut nxt_nodes = BTreeSet::new();
 
@@ -108,12 +700,12 @@ impl NFA {
 
         while !cur_nodes.is_empty() {
             for &node in &cur_nodes {
-                visited[node] = true;
+                visited.set(node, true);
                 for &byte in &self.alphabet {
                     if let Some(states) = self.states[node].transitions.get(&byte) {
                         nxt_nodes.extend(
                             states
-                                .into_iter()
+                                .iter()
                                 .filter(|&&n| !visited[n])
                                 .cloned()
                                 .collect::<BTreeSet<StateNumber>>(),
@@ -128,13 +720,237 @@ impl NFA {
         }
     }
 
+    /// Gives `state` a name, which is used instead of the state number in `dot` and next to it
+    ///  in `pretty_print`.
+    pub fn set_state_name(&mut self, state: StateNumber, name: impl Into<String>) {
+        assert!(state < self.states.len());
+        if self.state_names.len() <= state {
+            self.state_names.resize(state + 1, None);
+        }
+        self.state_names[state] = Some(name.into());
+    }
+
+    pub fn state_name(&self, state: StateNumber) -> Option<&str> {
+        self.state_names.get(state).and_then(|name| name.as_deref())
+    }
+
+    /// Computes the Aho-Corasick failure links of the trie. Once they are there, the `Automaton`
+    ///  implementation follows them where a state has no transition, and reports the patterns
+    ///  of the states along the failure links as matches too. So this is an alternative to
+    ///  `ignore_prefixes` for searching in a text, and shouldn't be combined with it.
+    pub fn build_failure_links(&mut self) {
+        self.failure_links = Some(self.compute_failure_links());
+    }
+
+    fn compute_failure_links(&self) -> Vec<StateNumber> {
+        let mut failure_links = vec![START; self.states.len()];
+        failure_links[STUCK] = STUCK;
+
+        // The visited states keep the search finite when the automaton has cycles, e.g. after
+        //  `ignore_postfixes`
+        let mut visited = BitVec::from_elem(self.states.len(), false);
+        visited.set(START, true);
+        let mut worklist = VecDeque::new();
+        worklist.push_back(START);
+        while let Some(state) = worklist.pop_front() {
+            for (&byte, to) in &self.states[state].transitions {
+                for &nxt_state in to {
+                    if visited[nxt_state] {
+                        continue;
+                    }
+                    visited.set(nxt_state, true);
+                    if state != START {
+                        let mut fallback = failure_links[state];
+                        loop {
+                            let target = self.states[fallback].transitions.get(&byte);
+                            if let Some(&target) = target.and_then(|t| t.iter().next()) {
+                                failure_links[nxt_state] = target;
+                                break;
+                            }
+                            if fallback == START {
+                                break;
+                            }
+                            fallback = failure_links[fallback];
+                        }
+                    }
+                    worklist.push_back(nxt_state);
+                }
+            }
+        }
+
+        failure_links
+    }
+
+    /// Whether the automaton is still the trie of its dictionary: every transition has a single
+    ///  target, and every state other than the start and stuck states is the target of exactly
+    ///  one transition. Operations like `ignore_prefixes`, `ignore_postfixes` and
+    ///  `case_fold_unicode` give up on that.
+    pub fn is_trie(&self) -> bool {
+        self.is_trie_with_twins(false)
+    }
+
+    // `is_trie`, where with `twins` a transition on an uppercase ASCII letter may also go where
+    //  the transition on the lowercase letter goes, as after `from_dictionary_nocase`
+    fn is_trie_with_twins(&self, twins: bool) -> bool {
+        if self.states.len() <= START {
+            return self.states.iter().all(|state| state.transitions.is_empty());
+        }
+        let mut targeted = BitVec::from_elem(self.states.len(), false);
+        targeted.set(START, true);
+        targeted.set(STUCK, true);
+        for state in &self.states {
+            if !state.epsilon.is_empty() {
+                return false;
+            }
+            for (&byte, to) in &state.transitions {
+                if twins
+                    && byte.is_ascii_uppercase()
+                    && state.transitions.get(&(byte ^ 0x20)) == Some(to)
+                {
+                    continue;
+                }
+                let mut to = to.iter();
+                match (to.next(), to.next()) {
+                    (Some(&to), None) if !targeted[to] => targeted.set(to, true),
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+
+    /// The Aho-Corasick goto table: for every state and byte the state to go to, following the
+    ///  failure links where the trie has no transition. This is a complete DFA transition table
+    ///  for searching in a text, as an alternative to `ignore_prefixes` followed by
+    ///  `powerset_construction`. It needs the automaton to be a trie, see `is_trie`.
+    pub fn compute_goto_function(&self) -> Result<Vec<[StateNumber; 256]>, ConversionError> {
+        if !self.is_trie() {
+            return Err(ConversionError::NotATrie);
+        }
+        let computed;
+        let failure_links = match &self.failure_links {
+            Some(failure_links) => failure_links,
+            None => {
+                computed = self.compute_failure_links();
+                &computed
+            }
+        };
+
+        let mut goto = vec![[STUCK; 256]; self.states.len()];
+        // Breadth first, so the row of the failure link of a state is always done before it
+        let mut worklist = VecDeque::new();
+        worklist.push_back(START);
+        while let Some(state) = worklist.pop_front() {
+            let mut row = if state == START {
+                [START; 256]
+            } else {
+                goto[failure_links[state]]
+            };
+            // In a trie every state is the target of one transition, so it is queued only once
+            for (&byte, to) in &self.states[state].transitions {
+                let to = *to
+                    .iter()
+                    .next()
+                    .expect("A trie has one target per transition");
+                row[byte as usize] = to;
+                worklist.push_back(to);
+            }
+            goto[state] = row;
+        }
+        for row in goto.iter_mut() {
+            fold_row(&self.fold_table, row);
+        }
+        Ok(goto)
+    }
+
+    #[inline]
+    fn fold(&self, byte: Input) -> Input {
+        match &self.fold_table {
+            Some(fold_table) => fold_table[byte as usize],
+            None => byte,
+        }
+    }
+
+    /// The complete deterministic automaton of the Aho-Corasick goto table, with the same states
+    ///  as this trie. Every state has a transition on all 256 bytes, found by following the
+    ///  failure links where the trie has none, and reports the patterns along its failure
+    ///  links. So it finds the same matches as `ignore_prefixes` followed by
+    ///  `powerset_construction`, without the powerset construction. The case folding table, if
+    ///  any, is baked into the transitions. Like `compute_goto_function`, this needs a trie.
+    pub fn goto_dnfa(&self) -> Result<NFA, ConversionError> {
+        let goto = self.compute_goto_function()?;
+        let computed;
+        let failure_links = match &self.failure_links {
+            Some(failure_links) => failure_links,
+            None => {
+                computed = self.compute_failure_links();
+                &computed
+            }
+        };
+        let states = goto
+            .iter()
+            .enumerate()
+            .map(|(state, row)| {
+                let mut pattern_ends = BTreeSet::new();
+                let mut fallback = state;
+                while fallback != STUCK {
+                    pattern_ends.extend(&self.states[fallback].pattern_ends);
+                    if fallback == START {
+                        break;
+                    }
+                    fallback = failure_links[fallback];
+                }
+                NFAState {
+                    transitions: (0..=255)
+                        .map(|byte: Input| (byte, [row[byte as usize]].iter().cloned().collect()))
+                        .collect(),
+                    epsilon: BTreeSet::new(),
+                    pattern_ends: pattern_ends.into_iter().collect(),
+                }
+            })
+            .collect();
+        Ok(NFA {
+            alphabet: (0..=255).collect(),
+            states,
+            dict: self.dict.clone(),
+            dict_index: self.dict_index.clone(),
+            priorities: self.priorities.clone(),
+            ..NFA::new()
+        })
+    }
+
+    /// The states whose pattern ends are matches when the automaton is in `state`: the chain of
+    ///  failure links down to the start state if there are failure links, otherwise only `state`.
+    fn match_states(&self, state: StateNumber) -> impl Iterator<Item = StateNumber> + '_ {
+        iter::successors(Some(state), move |&state| match &self.failure_links {
+            Some(failure_links) if state != START && state != STUCK => Some(failure_links[state]),
+            _ => None,
+        })
+    }
+
+    /// Lets matches start anywhere in the input, by adding a self-loop on every byte to the
+    ///  start state. This extends the alphabet to all 256 bytes, so a powerset construction
+    ///  afterwards looks at every byte in every state, even for a dictionary over a handful of
+    ///  bytes. See `ignore_prefixes_compact` for the cheaper variant.
     pub fn ignore_prefixes(&mut self) {
         self.alphabet = (0..=255).collect();
+        self.add_start_loops();
+    }
+
+    /// Like `ignore_prefixes`, but only adds self-loops on the bytes already in the alphabet, so
+    ///  the alphabet and the work of a later powerset construction stay the same.
+    /// The tradeoff: any byte outside the alphabet gets the search stuck, so this is only useful
+    ///  for haystacks that are known to consist of bytes from the dictionary.
+    pub fn ignore_prefixes_compact(&mut self) {
+        self.add_start_loops();
+    }
+
+    fn add_start_loops(&mut self) {
         for &byte in &self.alphabet {
             self.states[START]
                 .transitions
                 .entry(byte)
-                .or_insert_with(BTreeSet::new)
+                .or_default()
                 .insert(START);
         }
     }
@@ -148,61 +964,653 @@ impl NFA {
             .filter(|(_, st)| st.is_final());
         for (fin, state) in finals {
             for &byte in &self.alphabet {
-                state
+                state.transitions.entry(byte).or_default().insert(fin);
+            }
+        }
+    }
+
+    /// Lets every codepoint in the patterns also match its case-equivalent codepoints.
+    /// Patterns are interpreted as UTF-8; patterns that aren't valid UTF-8 are left alone.
+    /// Equivalents are added as extra byte paths between the trie states around the original
+    ///  codepoint. Multi-char expansions work both ways: a pattern `ß` also matches `SS`, and a
+    ///  pattern `ss` also matches `ß`.
+    /// This follows the trie paths of the dictionary, so it returns an error if the NFA is no
+    ///  longer a trie (see `is_trie`), e.g. after `ignore_prefixes` or an earlier call.
+    pub fn case_fold_unicode(&mut self) -> Result<(), ConversionError> {
+        if !self.is_trie() {
+            return Err(ConversionError::NotATrie);
+        }
+        let folding = case_folding();
+
+        // First find every (from, variant, to) step on the trie paths of the dictionary, so the
+        //  extra paths we add don't confuse the walk over the trie.
+        let mut steps = BTreeSet::new();
+        for bytes in &self.dict {
+            let text = match std::str::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            let chars: Vec<char> = text.chars().collect();
+            // The trie state before every char, and after the last one
+            let mut path = vec![START];
+            for &c in &chars {
+                let mut cur_state = *path.last().unwrap();
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    cur_state = *self.states[cur_state].transitions[&self.fold(byte)]
+                        .iter()
+                        .next()
+                        .expect("A trie has one target per transition");
+                }
+                path.push(cur_state);
+            }
+            for (i, &c) in chars.iter().enumerate() {
+                for variant in folding.variants(c) {
+                    steps.insert((path[i], variant, path[i + 1]));
+                }
+                // The chars that expand to the next few chars of the pattern
+                for len in 2..=MAX_EXPANSION.min(chars.len() - i) {
+                    for variant in folding.contractions(&chars[i..i + len]) {
+                        steps.insert((path[i], variant.to_string(), path[i + len]));
+                    }
+                }
+            }
+        }
+
+        let mut alphabet: BTreeSet<Input> = self.alphabet.iter().cloned().collect();
+        for (from, variant, to) in steps {
+            let bytes = variant.as_bytes();
+            alphabet.extend(bytes);
+            let (&last, init) = bytes.split_last().unwrap();
+            let mut cur_state = from;
+            for &byte in init {
+                let nxt_state = self.states.len();
+                self.states.push(NFAState::new());
+                self.states[cur_state]
                     .transitions
                     .entry(byte)
-                    .or_insert_with(BTreeSet::new)
-                    .insert(fin);
+                    .or_default()
+                    .insert(nxt_state);
+                cur_state = nxt_state;
             }
+            self.states[cur_state]
+                .transitions
+                .entry(last)
+                .or_default()
+                .insert(to);
         }
+        self.alphabet = alphabet.into_iter().collect();
+        self.depth_map.clear();
+        self.failure_links = None;
+        Ok(())
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn into_dfa(self) -> Result<DFA, ()> {
         let finals = BitVec::from_fn(self.states.len(), |i| self.states[i].is_final());
         let mut states = Vec::with_capacity(self.states.len());
         for state in self.states {
-            states.push(state.into_dfa()?);
+            states.push(state.into_dfa(&self.fold_table)?);
+        }
+        Ok(DFA::new(states.into_boxed_slice(), finals, self.dict).with_dict_index(self.dict_index))
+    }
+
+    /// Does `powerset_construction`, `into_dfa` and `DFA::minimize` in one go.
+    /// The powerset construction takes O(n·k) steps for every DNFA state it creates, for n NFA
+    ///  states and an alphabet of k bytes. Hopcroft's algorithm then takes O(s·log(s)) steps
+    ///  (times the 256 bytes of the transition table) for the s DFA states.
+    pub fn into_minimal_dfa(self) -> Result<DFA, ConversionError> {
+        let dfa = self
+            .powerset_construction()
+            .into_dfa()
+            .map_err(|()| ConversionError::NotDeterministic)?;
+        Ok(dfa.minimize())
+    }
+
+    /// The minimal deterministic automaton for this one, as an `NFA`: `powerset_construction`,
+    ///  `into_dfa` and `DFA::minimize`, converted back. Depth map, failure links and state names
+    ///  are not kept, and a fold table is applied to the transitions.
+    pub fn minimize(&self) -> NFA {
+        let dfa = self
+            .powerset_construction()
+            .into_dfa()
+            .expect("The powerset construction is deterministic")
+            .minimize();
+        let (dfa_states, dict, dict_index) = dfa.into_parts();
+        let mut alphabet = BTreeSet::new();
+        let states: Vec<NFAState> = dfa_states
+            .into_vec()
+            .into_iter()
+            .map(|state| {
+                let (row, pattern_ends) = state.into_parts();
+                let mut transitions = BTreeMap::new();
+                for (byte, &to) in row.iter().enumerate() {
+                    if to != STUCK {
+                        alphabet.insert(byte as Input);
+                        transitions.insert(byte as Input, [to].iter().cloned().collect());
+                    }
+                }
+                NFAState {
+                    transitions,
+                    pattern_ends,
+                    ..NFAState::new()
+                }
+            })
+            .collect();
+        NFA {
+            alphabet: alphabet.into_iter().collect(),
+            states,
+            dict,
+            dict_index,
+            priorities: self.priorities.clone(),
+            ..NFA::new()
+        }
+    }
+
+    /// Adds a transition from `from` to `to` that doesn't consume any input. Searching and the
+    ///  powerset construction follow these, but `into_dfa` refuses an automaton that has them.
+    pub fn add_epsilon_transition(&mut self, from: StateNumber, to: StateNumber) {
+        assert!(from < self.states.len());
+        assert!(to < self.states.len());
+        self.states[from].epsilon.insert(to);
+    }
+
+    /// `states` and all states that can be reached from them by epsilon transitions
+    pub fn epsilon_closure(&self, states: &BTreeSet<StateNumber>) -> BTreeSet<StateNumber> {
+        let mut closure = states.clone();
+        let mut worklist: Vec<StateNumber> = states.iter().cloned().collect();
+        while let Some(state) = worklist.pop() {
+            for &to in &self.states[state].epsilon {
+                if closure.insert(to) {
+                    worklist.push(to);
+                }
+            }
+        }
+        closure
+    }
+
+    // The epsilon closure, without a copy for the usual case of no epsilon transitions
+    fn closed<'s>(&self, states: &'s BTreeSet<StateNumber>) -> Cow<'s, BTreeSet<StateNumber>> {
+        if states
+            .iter()
+            .all(|&state| self.states[state].epsilon.is_empty())
+        {
+            Cow::Borrowed(states)
+        } else {
+            Cow::Owned(self.epsilon_closure(states))
+        }
+    }
+
+    // The patterns that end in any of `states`, sorted and without duplicates
+    fn pattern_ends_of(&self, states: &BTreeSet<StateNumber>) -> Vec<PatternNumber> {
+        let fin: BTreeSet<PatternNumber> = states
+            .iter()
+            .flat_map(|&st| self.states[st].pattern_ends.iter().cloned())
+            .collect();
+        fin.into_iter().collect()
+    }
+
+    // The patterns that match when the automaton is in `states`, sorted and without duplicates,
+    //  for the `LazyDFA` states
+    pub(crate) fn patterns_of(&self, states: &BTreeSet<StateNumber>) -> Vec<PatternNumber> {
+        let fin: BTreeSet<PatternNumber> = self
+            .closed(states)
+            .iter()
+            .flat_map(|&state| self.match_states(state))
+            .flat_map(|s| self.states[s].pattern_ends.iter().cloned())
+            .collect();
+        fin.into_iter().collect()
+    }
+
+    pub(crate) fn alphabet(&self) -> &[Input] {
+        &self.alphabet
+    }
+
+    pub(crate) fn match_len(&self, patt_no: PatternNumber) -> usize {
+        pattern_len(&self.dict, &self.dict_index, patt_no)
+    }
+
+    // A deterministic NFA over the same dictionary as this one, from a transition map and the
+    //  pattern ends per state, like the result of `powerset_construction`
+    pub(crate) fn deterministic_from_parts(
+        &self,
+        parts: Vec<(BTreeMap<Input, StateNumber>, Vec<PatternNumber>)>,
+    ) -> NFA {
+        let states = parts
+            .into_iter()
+            .map(|(transitions, pattern_ends)| NFAState {
+                transitions: transitions
+                    .into_iter()
+                    .map(|(input, to)| (input, [to].iter().cloned().collect()))
+                    .collect(),
+                epsilon: BTreeSet::new(),
+                pattern_ends,
+            })
+            .collect();
+        NFA {
+            alphabet: self.alphabet.clone(),
+            states,
+            dict: self.dict.clone(),
+            dict_index: self.dict_index.clone(),
+            fold_table: self.fold_table,
+            nocase: self.nocase,
+            priorities: self.priorities.clone(),
+            ..NFA::new()
+        }
+    }
+
+    fn close(&self, states: BTreeSet<StateNumber>) -> BTreeSet<StateNumber> {
+        match self.closed(&states) {
+            Cow::Borrowed(_) => states,
+            Cow::Owned(closure) => closure,
         }
-        Ok(DFA::new(states.into_boxed_slice(), finals, self.dict))
     }
 
     pub fn apply(&self, input: &[Input]) -> Vec<PatternNumber> {
-        let mut cur_states = BTreeSet::new();
+        self.apply_mode(input, MatchMode::All)
+    }
+
+    /// `apply`, but with a choice of which of the accepted patterns to return
+    pub fn apply_mode(&self, input: &[Input], mode: MatchMode) -> Vec<PatternNumber> {
+        let end_states = self.apply_states(input);
+        let mut accepted = end_states
+            .iter()
+            .flat_map(|&state| self.states[state].pattern_ends.iter().cloned());
+        match mode {
+            MatchMode::First => accepted.next().into_iter().collect(),
+            MatchMode::All => accepted.collect(),
+            MatchMode::Longest => {
+                let accepted: Vec<PatternNumber> = accepted.collect();
+                let len =
+                    |&patt_no: &PatternNumber| pattern_len(&self.dict, &self.dict_index, patt_no);
+                let longest = accepted.iter().map(len).max();
+                accepted
+                    .into_iter()
+                    .filter(|patt_no| Some(len(patt_no)) == longest)
+                    .collect()
+            }
+        }
+    }
+
+    fn apply_states(&self, input: &[Input]) -> BTreeSet<StateNumber> {
+        let mut cur_states = self.start_state();
         let mut nxt_states = BTreeSet::new();
-        cur_states.insert(START);
         for &byte in input {
+            let byte = self.fold(byte);
             for cur_state in cur_states {
                 if let Some(nxts) = self.states[cur_state].transitions.get(&byte) {
                     nxt_states.extend(nxts);
                 }
             }
-            cur_states = nxt_states;
+            cur_states = self.close(nxt_states);
             nxt_states = BTreeSet::new();
         }
         cur_states
+    }
+
+    /// `apply` for every input in a batch
+    pub fn apply_multi(&self, inputs: &[&[Input]]) -> Vec<Vec<PatternNumber>> {
+        inputs.iter().map(|input| self.apply(input)).collect()
+    }
+
+    // The states that `state` links to: the targets of its transitions in byte order, then
+    //  those of its epsilon transitions, then its failure link
+    fn successors(&self, state: StateNumber) -> impl Iterator<Item = StateNumber> + '_ {
+        let failure_link = self.failure_links.as_ref().map(|links| links[state]);
+        self.states[state]
+            .transitions
+            .values()
+            .flatten()
+            .chain(&self.states[state].epsilon)
+            .cloned()
+            .chain(failure_link)
+    }
+
+    /// The states that can be reached from the start state by transitions, epsilon transitions
+    ///  or failure links, in breadth-first order.
+    /// Missing transitions don't count as transitions to the stuck state.
+    pub fn reachable_states(&self) -> Vec<StateNumber> {
+        let mut visited = BitVec::from_elem(self.states.len(), false);
+        visited.set(START, true);
+        let mut order = vec![START];
+        let mut next = 0;
+        while next < order.len() {
+            let state = order[next];
+            next += 1;
+            for nxt_state in self.successors(state) {
+                if !visited[nxt_state] {
+                    visited.set(nxt_state, true);
+                    order.push(nxt_state);
+                }
+            }
+        }
+        order
+    }
+
+    /// The number of states, including the stuck state
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// The number of (state, byte) pairs that have a transition, however many states the
+    ///  transition goes to
+    pub fn transition_count(&self) -> usize {
+        self.states
+            .iter()
+            .map(|state| state.transitions.len())
+            .sum()
+    }
+
+    /// The number of bytes in the alphabet, which is 256 after `ignore_prefixes`
+    pub fn alphabet_size(&self) -> usize {
+        self.alphabet.len()
+    }
+
+    /// The total length of the patterns in the dictionary
+    pub fn dict_byte_count(&self) -> usize {
+        self.dict.iter().map(|bytes| bytes.len()).sum()
+    }
+
+    /// Removes the states that can't be reached from the start state, by transitions, epsilon
+    ///  transitions or failure links, and renumbers the rest in their original order. The
+    ///  stuck state is always kept.
+    pub fn prune_unreachable_states(&mut self) {
+        if self.states.is_empty() {
+            return;
+        }
+        let mut reachable = BitVec::from_elem(self.states.len(), false);
+        reachable.set(STUCK, true);
+        reachable.set(START, true);
+        let mut queue: VecDeque<StateNumber> = vec![START].into();
+        while let Some(state) = queue.pop_front() {
+            for nxt_state in self.successors(state) {
+                if !reachable[nxt_state] {
+                    reachable.set(nxt_state, true);
+                    queue.push_back(nxt_state);
+                }
+            }
+        }
+
+        let mut renumber = vec![STUCK; self.states.len()];
+        let mut kept = 0;
+        for (state, new_number) in renumber.iter_mut().enumerate() {
+            if reachable[state] {
+                *new_number = kept;
+                kept += 1;
+            }
+        }
+        if kept == self.states.len() {
+            return;
+        }
+
+        let states = std::mem::take(&mut self.states);
+        self.states = states
+            .into_iter()
+            .enumerate()
+            .filter(|&(state, _)| reachable[state])
+            .map(|(_, state)| NFAState {
+                transitions: state
+                    .transitions
+                    .into_iter()
+                    .map(|(input, to)| (input, to.into_iter().map(|s| renumber[s]).collect()))
+                    .collect(),
+                epsilon: state.epsilon.into_iter().map(|s| renumber[s]).collect(),
+                pattern_ends: state.pattern_ends,
+            })
+            .collect();
+        for states in self.depth_map.values_mut() {
+            *states = states
+                .iter()
+                .filter(|&&state| reachable[state])
+                .map(|&state| renumber[state])
+                .collect();
+        }
+        if let Some(failure_links) = &mut self.failure_links {
+            *failure_links = failure_links
+                .iter()
+                .enumerate()
+                .filter(|&(state, _)| reachable[state])
+                .map(|(_, &link)| renumber[link])
+                .collect();
+        }
+        self.state_names = self
+            .state_names
+            .drain(..)
+            .enumerate()
+            .filter(|&(state, _)| reachable[state])
+            .map(|(_, name)| name)
+            .collect();
+    }
+
+    /// The length of the shortest pattern, or `None` if the dictionary is empty
+    pub fn shortest_pattern_len(&self) -> Option<usize> {
+        self.dict.iter().map(|p| p.len()).min()
+    }
+
+    /// The length of the longest pattern, or `None` if the dictionary is empty
+    pub fn longest_pattern_len(&self) -> Option<usize> {
+        self.dict.iter().map(|p| p.len()).max()
+    }
+
+    /// The average length of the patterns, or `0.0` if the dictionary is empty
+    pub fn average_pattern_len(&self) -> f64 {
+        if self.dict.is_empty() {
+            return 0.0;
+        }
+        let total: usize = self.dict.iter().map(|p| p.len()).sum();
+        total as f64 / self.dict.len() as f64
+    }
+
+    /// The states that can be reached from the start state, in breadth-first order. This is the
+    ///  same as `reachable_states`, under a name that goes with `depth_first_order`.
+    pub fn breadth_first_order(&self) -> Vec<StateNumber> {
+        self.reachable_states()
+    }
+
+    /// The states that can be reached from the start state, in depth-first pre-order. The
+    ///  transitions of a state are followed in byte order, and then its epsilon transitions and
+    ///  failure link.
+    pub fn depth_first_order(&self) -> Vec<StateNumber> {
+        let mut visited = BitVec::from_elem(self.states.len(), false);
+        let mut order = Vec::new();
+        let mut stack = vec![START];
+        while let Some(state) = stack.pop() {
+            if visited[state] {
+                continue;
+            }
+            visited.set(state, true);
+            order.push(state);
+            let successors: Vec<StateNumber> = self.successors(state).collect();
+            stack.extend(successors.into_iter().rev().filter(|&s| !visited[s]));
+        }
+        order
+    }
+
+    /// The transitions as a `[state][byte]` table, with an empty set where there is no
+    ///  transition. For tools that want the automaton as plain arrays.
+    pub fn transitions_matrix(&self) -> Vec<Vec<BTreeSet<StateNumber>>> {
+        self.states
+            .iter()
+            .map(|state| {
+                (0..=255)
+                    .map(|byte| {
+                        state
+                            .transitions
+                            .get(&byte)
+                            .map(|to| to.iter().cloned().collect())
+                            .unwrap_or_default()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Like `transitions_matrix`, but with only the first target state of every transition.
+    /// This only loses information if the automaton is not deterministic, e.g. after
+    ///  `powerset_construction` it is the complete transition table.
+    pub fn transitions_matrix_single(&self) -> Vec<Vec<Option<StateNumber>>> {
+        self.states
             .iter()
-            .flat_map(|&state| self.states[state].pattern_ends.clone())
+            .map(|state| {
+                (0..=255)
+                    .map(|byte| {
+                        state
+                            .transitions
+                            .get(&byte)
+                            .and_then(|to| to.iter().next().cloned())
+                    })
+                    .collect()
+            })
             .collect()
     }
 
+    /// Finds the match that starts first in `haystack`, even if another match ends before it.
+    /// Ties are broken by the order in which the matches are found. Like `find`, this only looks
+    ///  beyond the start of the haystack after `ignore_prefixes` or `build_failure_links`.
+    pub fn find_earliest(&self, haystack: &[Input]) -> Option<Match> {
+        let longest = self.longest_pattern_len().unwrap_or(0);
+        let mut best: Option<Match> = None;
+        let mut states = self.start_state();
+        for (i, byte) in haystack.iter().enumerate() {
+            let offset = i + 1;
+            // Matches that end here or later start at `offset - longest` or later
+            if let Some(best) = best {
+                if offset > best.start + longest {
+                    break;
+                }
+            }
+            states = self.next_state(&states, byte);
+            for &state in &states {
+                for s in self.match_states(state) {
+                    for &patt_no in &self.states[s].pattern_ends {
+                        let start = offset - pattern_len(&self.dict, &self.dict_index, patt_no);
+                        if best.is_none_or(|best| start < best.start) {
+                            best = Some(Match {
+                                payload: patt_no,
+                                start,
+                                end: offset,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// The bytes that can start a match: `true` for every byte on which the start state moves to
+    ///  a state other than itself. Any other byte leaves a search that is in the start state
+    ///  where it is, which lets `find_with_prefilter` skip over it.
+    /// If the start state is final (the empty pattern is in the dictionary) or has epsilon
+    ///  transitions, every byte is in the prefilter.
+    pub fn prefilter_bytes(&self) -> [bool; 256] {
+        if self.states[START].is_final() || !self.states[START].epsilon.is_empty() {
+            return [true; 256];
+        }
+        let mut present = [false; 256];
+        for (&byte, to) in &self.states[START].transitions {
+            if to.iter().any(|&state| state != START) {
+                present[byte as usize] = true;
+            }
+        }
+        let mut prefilter = [false; 256];
+        for byte in 0..=255 {
+            prefilter[byte as usize] = present[self.fold(byte) as usize];
+        }
+        prefilter
+    }
+
+    /// Like `find`, but while the search is in the start state it skips ahead to the next byte
+    ///  in `prefilter_bytes`. This pays off on haystacks where few bytes can start a match.
+    /// Skipping is only done when every other byte keeps the search in the start state, i.e.
+    ///  after `ignore_prefixes` or `build_failure_links`; otherwise this is the same as `find`.
+    pub fn find_with_prefilter<'i, 'a>(
+        &'a self,
+        haystack: &'i [Input],
+    ) -> PrefilterMatches<'i, 'a> {
+        let prefilter = self.prefilter_bytes();
+        let start = self.start_state();
+        let skip = (0..=255)
+            .filter(|&byte: &Input| !prefilter[byte as usize])
+            .all(|byte| self.next_state(&start, &byte) == start);
+        PrefilterMatches {
+            nfa: self,
+            input: haystack,
+            offset: 0,
+            state: start.clone(),
+            start,
+            prefilter,
+            skip,
+        }
+    }
+
+    /// Like `find`, but of the patterns that end at a position only the one with the highest
+    ///  `priority_of` is reported. Between patterns with the same priority, the first wins.
+    pub fn find_priority<'i, 'a>(&'a self, s: &'i [Input]) -> PriorityMatches<'i, 'a> {
+        PriorityMatches {
+            nfa: self,
+            input: s,
+            offset: 0,
+            state: self.start_state(),
+        }
+    }
+
+    /// Checks that every pattern in the dictionary is still accepted by the automaton, which
+    ///  should hold after any of the transformations on this type.
+    /// Returns the numbers of the patterns that are no longer accepted.
+    pub fn verify_dict_completeness(&self) -> Result<(), Vec<PatternNumber>> {
+        let mut ids: Vec<PatternNumber> = (0..self.dict.len()).collect();
+        if let Some(dict_index) = &self.dict_index {
+            for (&patt_no, &index) in dict_index {
+                ids[index] = patt_no;
+            }
+        }
+        let missing: Vec<PatternNumber> = self
+            .dict
+            .iter()
+            .zip(ids)
+            .filter(|&(bytes, patt_no)| !self.apply(bytes).contains(&patt_no))
+            .map(|(_, patt_no)| patt_no)
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    pub fn powerset_construction(&self) -> Self {
+        // With at most 64 states (including start and stuck), a set of states fits in a u64
+        if self.states.len() <= 64 {
+            self.powerset_construction_small()
+        } else {
+            self.powerset_construction_general()
+        }
+    }
+
     // Changed from a recursive algorithm to a worklist (stack) algorithm
     // i.e., it keeps its own stack instead of using the function stack
-    pub fn powerset_construction(&self) -> Self {
+    #[doc(hidden)]
+    pub fn powerset_construction_general(&self) -> Self {
         // dnfa setup, two states: start and stuck, already in there
         let mut dnfa = NFA {
             alphabet: self.alphabet.clone(),
             states: Vec::with_capacity(self.states.len()),
             dict: self.dict.clone(),
-            depth_map: BTreeMap::new(),
+            dict_index: self.dict_index.clone(),
+            fold_table: self.fold_table,
+            nocase: self.nocase,
+            priorities: self.priorities.clone(),
+            ..NFA::new()
         };
         dnfa.states.push(NFAState::new());
         dnfa.states.push(NFAState::new());
         // Maps sets of state-numbers from the NFA, to state-numbers of the DNFA
-        let mut states_map: HashMap<Vec<StateNumber>, StateNumber> = HashMap::new();
+        let mut states_map: StatesMap<Vec<StateNumber>> = StatesMap::default();
         // Set of states that the NFA is in
-        let cur_states: BTreeSet<StateNumber> = [START].into_iter().cloned().collect();
+        let cur_states: BTreeSet<StateNumber> = self.start_state();
 
-        dnfa.states[START].pattern_ends = self.states[START].pattern_ends.clone();
+        dnfa.states[START].pattern_ends = self.pattern_ends_of(&cur_states);
 
         // While executing an NFA, no states means we're stuck,
         states_map.insert(Vec::new(), STUCK);
@@ -210,6 +1618,7 @@ impl NFA {
         states_map.insert(vec![STUCK], STUCK);
         // start state only means we're at the start.
         states_map.insert(vec![START], START);
+        states_map.insert(cur_states.iter().cloned().collect(), START);
 
         // The "recursive" part. We start in only the start state.
         // For every item (nfa-state-set, dfa-state), we go over every symbol in the alphabet.
@@ -224,15 +1633,12 @@ impl NFA {
         while let Some((cur_states, cur_num)) = worklist.pop() {
             for &input in &dnfa.alphabet {
                 let mut nxt_states = BTreeSet::new();
-                let mut fin = BTreeSet::new();
                 for &cur_state in &cur_states {
                     if let Some(states) = self.states[cur_state].transitions.get(&input) {
                         nxt_states.extend(states);
-                        for &st in states {
-                            fin.extend(self.states[st].pattern_ends.clone());
-                        }
                     }
                 }
+                let nxt_states = self.close(nxt_states);
                 let nxt_states_vec: Vec<StateNumber> = nxt_states.clone().into_iter().collect();
 
                 let nxt_num = {
@@ -240,7 +1646,7 @@ impl NFA {
                     states_map.get(&nxt_states_vec).cloned().unwrap_or_else(|| {
                         let nxt_num = dnfa_states.len();
                         let mut new_state = NFAState::new();
-                        new_state.pattern_ends = fin.into_iter().collect();
+                        new_state.pattern_ends = self.pattern_ends_of(&nxt_states);
                         dnfa_states.push(new_state);
                         states_map.insert(nxt_states_vec, nxt_num);
                         if nxt_num != STUCK {
@@ -253,13 +1659,171 @@ impl NFA {
                 dnfa.states[cur_num]
                     .transitions
                     .entry(input)
-                    .or_insert_with(BTreeSet::new)
+                    .or_default()
+                    .insert(nxt_num);
+            }
+        }
+        dnfa
+    }
+
+    // The same algorithm as `powerset_construction_general`, but with the sets of NFA states as
+    //  bitmasks, where bit `i` stands for state `i`. Only for NFAs with at most 64 states.
+    #[doc(hidden)]
+    pub fn powerset_construction_small(&self) -> Self {
+        assert!(self.states.len() <= 64);
+
+        let mut dnfa = NFA {
+            alphabet: self.alphabet.clone(),
+            states: Vec::with_capacity(self.states.len()),
+            dict: self.dict.clone(),
+            dict_index: self.dict_index.clone(),
+            fold_table: self.fold_table,
+            nocase: self.nocase,
+            priorities: self.priorities.clone(),
+            ..NFA::new()
+        };
+        dnfa.states.push(NFAState::new());
+        dnfa.states.push(NFAState::new());
+        let mut states_map: StatesMap<u64> = StatesMap::default();
+
+        // The epsilon closure of every state, if there are any epsilon transitions
+        let closures: Option<Vec<u64>> = if self.states.iter().any(|st| !st.epsilon.is_empty()) {
+            Some(
+                (0..self.states.len())
+                    .map(|state| {
+                        self.epsilon_closure(&[state].iter().cloned().collect())
+                            .into_iter()
+                            .fold(0, |bits, st| bits | 1 << st)
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        let start_states = closures.as_ref().map_or(1 << START, |c| c[START]);
+        let pattern_ends_of = |mut bits: u64| {
+            let mut fin = BTreeSet::new();
+            while bits != 0 {
+                let st = bits.trailing_zeros() as StateNumber;
+                bits &= bits - 1;
+                fin.extend(&self.states[st].pattern_ends);
+            }
+            fin.into_iter().collect()
+        };
+
+        dnfa.states[START].pattern_ends = pattern_ends_of(start_states);
+
+        states_map.insert(0, STUCK);
+        states_map.insert(1 << STUCK, STUCK);
+        states_map.insert(1 << START, START);
+        states_map.insert(start_states, START);
+
+        let mut worklist = vec![(start_states, START)];
+        while let Some((cur_states, cur_num)) = worklist.pop() {
+            for &input in &dnfa.alphabet {
+                let mut nxt_states = 0u64;
+                let mut cur_bits = cur_states;
+                while cur_bits != 0 {
+                    let cur_state = cur_bits.trailing_zeros() as StateNumber;
+                    cur_bits &= cur_bits - 1;
+                    if let Some(states) = self.states[cur_state].transitions.get(&input) {
+                        for &st in states {
+                            nxt_states |= match &closures {
+                                Some(closures) => closures[st],
+                                None => 1 << st,
+                            };
+                        }
+                    }
+                }
+
+                let nxt_num = match states_map.get(&nxt_states) {
+                    Some(&nxt_num) => nxt_num,
+                    None => {
+                        let nxt_num = dnfa.states.len();
+                        let mut new_state = NFAState::new();
+                        new_state.pattern_ends = pattern_ends_of(nxt_states);
+                        dnfa.states.push(new_state);
+                        states_map.insert(nxt_states, nxt_num);
+                        worklist.push((nxt_states, nxt_num));
+                        nxt_num
+                    }
+                };
+
+                dnfa.states[cur_num]
+                    .transitions
+                    .entry(input)
+                    .or_default()
                     .insert(nxt_num);
             }
         }
         dnfa
     }
 
+    /// A compact table of the automaton, one row per state: the state number, whether it's the
+    ///  start or stuck state, its depth (if `add_depth_map` was called), a `*` if it's final, and
+    ///  its transitions grouped by target states. Rows are cut off at 120 columns.
+    pub fn pretty_print(&self) -> String {
+        const MAX_WIDTH: usize = 120;
+
+        let mut depths = BTreeMap::new();
+        for (&depth, states) in &self.depth_map {
+            for &state in states {
+                depths.entry(state).or_insert(depth);
+            }
+        }
+
+        let mut out = format!(
+            "{:>5} {:<5} {:>5} {:^3} transitions\n",
+            "state", "", "depth", "fin"
+        );
+        for (i, state) in self.states.iter().enumerate() {
+            let marker = match i {
+                START => "START",
+                STUCK => "STUCK",
+                _ => "",
+            };
+            let marker = match self.state_name(i) {
+                Some(name) => format!("{} {}", marker, name).trim_start().to_string(),
+                None => marker.to_string(),
+            };
+            let depth = depths
+                .get(&i)
+                .map_or_else(|| "-".to_string(), |d| d.to_string());
+            let fin = if state.is_final() { "*" } else { "" };
+
+            let mut grouped: BTreeMap<&StateSet, BTreeSet<Input>> = BTreeMap::new();
+            for (&byte, to) in &state.transitions {
+                grouped.entry(to).or_default().insert(byte);
+            }
+            let transitions = grouped
+                .into_iter()
+                .map(|(to, bytes)| {
+                    let to: Vec<String> = to.iter().map(|s| s.to_string()).collect();
+                    format!(
+                        "{} -> {{{}}}",
+                        implode_ranges(bytes).join(", "),
+                        to.join(", ")
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            let row = format!(
+                "{:>5} {:<5} {:>5} {:^3} {}",
+                i, marker, depth, fin, transitions
+            );
+            let row = row.trim_end();
+            if row.chars().count() > MAX_WIDTH {
+                out.extend(row.chars().take(MAX_WIDTH - 3));
+                out.push_str("...");
+            } else {
+                out.push_str(row);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
     #[doc(hidden)]
     pub fn dot(&self, options: DotOptions) -> String {
         use std::fmt::Write;
@@ -268,26 +1832,7 @@ impl NFA {
             ($($tt:tt)*) => { {write!(out, $($tt)*)}.unwrap() }
         }
 
-        let dict_comma_string = self
-            .dict
-            .clone()
-            .into_iter()
-            .map(|x| String::from_utf8_lossy(x.as_ref()).into_owned())
-            .collect::<Vec<String>>()
-            .join(", ");
-
-        w!(
-            r#"
-digraph automaton {{
-    label=<<FONT POINT-SIZE="20">{}</FONT>>;
-    labelloc="l";
-    labeljust="l";
-    rankdir="LR";
-    start [shape="none", label="", width=0];
-    start -> 0;
-"#,
-            dict_comma_string
-        );
+        dot_header(&mut out, &self.dict, &options.label);
 
         let mut original_edges = BTreeSet::new();
 
@@ -303,16 +1848,40 @@ digraph automaton {{
             }
         }
 
-        for (from, state) in (*self.states).into_iter().enumerate() {
+        for (from, state) in self.states.iter().enumerate() {
             if options.suppress_stuck_state && from == STUCK {
                 continue;
             }
             w!("    {}", from);
-            if from == STUCK {
-                w!(r#" [label="⊥"]"#);
+            let labels = match &options.labels {
+                Some(labels) if state.is_final() => Some(
+                    state
+                        .pattern_ends
+                        .iter()
+                        .filter_map(|&patt_no| labels.get(patt_no).map(String::as_str))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+                _ => None,
+            };
+            match (self.state_name(from), labels) {
+                (Some(name), None) => w!(" [label=\"{}\"]", name.replace('"', "\\\"")),
+                (Some(name), Some(labels)) => w!(
+                    " [label=\"{}: {}\"]",
+                    name.replace('"', "\\\""),
+                    labels.replace('"', "\\\"")
+                ),
+                (None, Some(labels)) => {
+                    w!(" [label=\"{}: {}\"]", from, labels.replace('"', "\\\""))
+                }
+                (None, None) if from == STUCK => w!(r#" [label="⊥"]"#),
+                (None, None) => {}
             }
             if self.states[from].is_final() {
                 w!(" [peripheries=2]");
+                if options.show_pattern_ends {
+                    w!("{}", dot_pattern_ends(&self.states[from].pattern_ends));
+                }
             }
             w!(";\n");
             let flipped_transitions = flip_multimap(state.transitions.clone());
@@ -324,31 +1893,353 @@ digraph automaton {{
                 if options.bold_dict_edges && original_edges.contains(&(from, to)) {
                     w!(" [style=bold]");
                 }
-                w!(" [label=\"{}\"];\n", implode_ranges(bytes).join(", "));
+                w!(" [label=\"{}\"];\n", implode_ranges(bytes).join(", "));
+            }
+        }
+
+        w!("}}");
+        out
+    }
+
+    /// Reads back the output of `dot`: the states, their transitions, which states are final,
+    ///  state names and the dictionary from the graph label (so not with a custom `label`, nor
+    ///  with patterns that contain `", "` or a line break).
+    /// The pattern ends are read from the `show_pattern_ends` labels. Without those, a pattern
+    ///  ends in the final states that its bytes lead to from the start state, and that are as
+    ///  many transitions away from the start state as the pattern is long. That holds for the
+    ///  tries of `from_dictionary`, also after `ignore_prefixes` or `ignore_postfixes`.
+    /// Epsilon transitions don't show in the DOT, so they're lost.
+    pub fn from_dot(s: &str) -> Result<NFA, DotParseError> {
+        let invalid = |line: &str| DotParseError::InvalidLabel(line.to_string());
+        let mut lines = s.lines().map(str::trim);
+        if !lines.any(|line| line == "digraph automaton {") {
+            return Err(DotParseError::UnexpectedEof);
+        }
+
+        let mut nfa = NFA::new();
+        let mut finals = BTreeSet::new();
+        let mut pattern_ends: BTreeMap<StateNumber, Vec<PatternNumber>> = BTreeMap::new();
+        let mut names = Vec::new();
+        let mut complete = false;
+        let ensure_state = |nfa: &mut NFA, state: StateNumber| {
+            while nfa.states.len() <= state.max(START) {
+                nfa.states.push(NFAState::new());
+            }
+        };
+        for line in lines {
+            if line == "}" {
+                complete = true;
+                break;
+            }
+            if let Some(label) = line.strip_prefix("label=<<FONT POINT-SIZE=\"20\">") {
+                let label = label
+                    .strip_suffix("</FONT>>;")
+                    .ok_or_else(|| invalid(line))?;
+                let label = label
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&");
+                if !label.is_empty() {
+                    nfa.dict = label.split(", ").map(|p| p.as_bytes().to_vec()).collect();
+                }
+                continue;
+            }
+            if line.is_empty()
+                || line.starts_with("labelloc=")
+                || line.starts_with("labeljust=")
+                || line.starts_with("rankdir=")
+                || line.starts_with("start ")
+            {
+                continue;
+            }
+
+            let end = line
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(line.len());
+            let from: StateNumber = line[..end].parse().map_err(|_| invalid(line))?;
+            let rest = line[end..].strip_suffix(';').ok_or_else(|| invalid(line))?;
+            ensure_state(&mut nfa, from);
+            if let Some(rest) = rest.strip_prefix(" -> ") {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let to: StateNumber = rest[..end].parse().map_err(|_| invalid(line))?;
+                ensure_state(&mut nfa, to);
+                // The byte ranges aren't escaped, a `"` in them is written as is
+                let label = rest[end..]
+                    .strip_suffix("\"]")
+                    .and_then(|rest| rest.find(" [label=\"").map(|i| &rest[i + 9..]))
+                    .ok_or_else(|| invalid(line))?;
+                let bytes = explode_ranges(label)
+                    .ok_or_else(|| DotParseError::InvalidRangeSyntax(label.to_string()))?;
+                for byte in bytes {
+                    if !nfa.states[from]
+                        .transitions
+                        .entry(byte)
+                        .or_default()
+                        .insert(to)
+                    {
+                        return Err(DotParseError::DuplicateEdge { from, to, byte });
+                    }
+                }
+                continue;
+            }
+
+            let mut rest = rest;
+            while let Some(attr) = rest.strip_prefix(" [") {
+                let (key, value) = attr.split_at(attr.find('=').ok_or_else(|| invalid(line))?);
+                let raw = &value[1..];
+                let (value, len) = if let Some(quoted) = raw.strip_prefix('"') {
+                    let mut unescaped = String::new();
+                    let mut chars = quoted.char_indices();
+                    let len = loop {
+                        match chars.next() {
+                            Some((_, '\\')) => {
+                                unescaped.extend(chars.next().map(|(_, c)| c));
+                            }
+                            Some((i, '"')) => break i + 2,
+                            Some((_, c)) => unescaped.push(c),
+                            None => return Err(invalid(line)),
+                        }
+                    };
+                    (unescaped, len)
+                } else {
+                    let len = raw.find(']').ok_or_else(|| invalid(line))?;
+                    (raw[..len].to_string(), len)
+                };
+                rest = raw[len..].strip_prefix(']').ok_or_else(|| invalid(line))?;
+                match key {
+                    "label" if from == STUCK && value == "⊥" => {}
+                    "label" => names.push((from, value)),
+                    "peripheries" if value == "2" => {
+                        finals.insert(from);
+                    }
+                    "xlabel" => {
+                        let patt_nos = value
+                            .split(", ")
+                            .map(|p| p.parse().map_err(|_| invalid(line)))
+                            .collect::<Result<_, _>>()?;
+                        pattern_ends.insert(from, patt_nos);
+                    }
+                    _ => return Err(invalid(line)),
+                }
+            }
+            if !rest.is_empty() {
+                return Err(invalid(line));
+            }
+        }
+        if !complete {
+            return Err(DotParseError::UnexpectedEof);
+        }
+        ensure_state(&mut nfa, START);
+
+        let mut alphabet = BTreeSet::new();
+        for state in &nfa.states {
+            alphabet.extend(state.transitions.keys());
+        }
+        nfa.alphabet = alphabet.into_iter().collect();
+        for (state, name) in names {
+            nfa.set_state_name(state, name);
+        }
+
+        // The number of transitions from the start state to every state, for the pattern ends
+        let mut distance = vec![None; nfa.states.len()];
+        let mut queue = VecDeque::new();
+        distance[START] = Some(0);
+        queue.push_back(START);
+        while let Some(state) = queue.pop_front() {
+            for to in nfa.states[state].transitions.values().flatten() {
+                if distance[*to].is_none() {
+                    distance[*to] = Some(distance[state].unwrap() + 1);
+                    queue.push_back(*to);
+                }
+            }
+        }
+        if !pattern_ends.is_empty() {
+            for (state, patt_nos) in pattern_ends {
+                nfa.states[state].pattern_ends = patt_nos;
+            }
+            return Ok(nfa);
+        }
+        for (patt_no, bytes) in nfa.dict.iter().enumerate() {
+            let mut cur_states: BTreeSet<StateNumber> = BTreeSet::new();
+            cur_states.insert(START);
+            for byte in bytes {
+                cur_states = cur_states
+                    .iter()
+                    .filter_map(|&state| nfa.states[state].transitions.get(byte))
+                    .flatten()
+                    .cloned()
+                    .collect();
+            }
+            for state in cur_states {
+                if finals.contains(&state) && distance[state] == Some(bytes.len()) {
+                    nfa.states[state].pattern_ends.push(patt_no);
+                }
+            }
+        }
+        Ok(nfa)
+    }
+}
+
+// The length of pattern `patt_no`, also for the `DFA` and `DDFA` that inherit the dictionary
+pub(crate) fn pattern_len(
+    dict: &[Vec<Input>],
+    dict_index: &Option<HashMap<PatternNumber, usize>>,
+    patt_no: PatternNumber,
+) -> usize {
+    match dict_index {
+        Some(dict_index) => dict[dict_index[&patt_no]].len(),
+        None => dict[patt_no].len(),
+    }
+}
+
+// Makes every byte in a row of transitions go where its canonical byte goes
+fn fold_row(fold_table: &Option<[Input; 256]>, row: &mut [StateNumber; 256]) {
+    if let Some(fold_table) = fold_table {
+        let unfolded = *row;
+        for (to, &canonical) in row.iter_mut().zip(fold_table.iter()) {
+            *to = unfolded[canonical as usize];
+        }
+    }
+}
+
+/// Follows `bytes` through the trie from `from`, adding states for the part that isn't there yet.
+/// Returns the state at the end of the path.
+fn insert_trie_path(states: &mut Vec<NFAState>, from: StateNumber, bytes: &[Input]) -> StateNumber {
+    let mut cur_state = from;
+    for &byte in bytes {
+        // If there is a transition on this byte from the cur_state
+        //  just go there. (We can be sure there will be only one at this point)
+        if let Some(&state) = states[cur_state]
+            .transitions
+            .get(&byte)
+            .and_then(|x| x.iter().next())
+        {
+            cur_state = state;
+        }
+        // Otherwise add a new transition, and add the corresponding state
+        else {
+            let nxt_state = states.len();
+            states.push(NFAState::new());
+            states[cur_state]
+                .transitions
+                .entry(byte)
+                .or_default()
+                .insert(nxt_state);
+            cur_state = nxt_state;
+        }
+    }
+    cur_state
+}
+
+// The most chars that the case mapping of a single char expands to
+const MAX_EXPANSION: usize = 3;
+
+/// Unicode case folding, derived from std's char case mappings
+struct CaseFolding {
+    // Every cased char to the other chars in its simple case folding class,
+    //  e.g. `k` to `K` and `K` (Kelvin sign), `s` to `S` and `ſ` (long s)
+    classes: HashMap<char, Vec<char>>,
+    // The class keys of a multi-char case mapping to the chars with that mapping,
+    //  e.g. `ss` to `ß`
+    expansions: HashMap<Vec<char>, Vec<char>>,
+}
+
+/// The case folding tables, computed once on first use
+fn case_folding() -> &'static CaseFolding {
+    static CASE_FOLDING: OnceLock<CaseFolding> = OnceLock::new();
+    CASE_FOLDING.get_or_init(|| {
+        let mut classes: HashMap<char, Vec<char>> = HashMap::new();
+        let mut expansions: HashMap<Vec<char>, Vec<char>> = HashMap::new();
+        for c in (0..=char::MAX as u32).filter_map(std::char::from_u32) {
+            classes.entry(class_key(c)).or_default().push(c);
+            for mapping in [
+                c.to_uppercase().collect::<Vec<char>>(),
+                c.to_lowercase().collect(),
+            ] {
+                if mapping.len() > 1 {
+                    let key = mapping.into_iter().map(class_key).collect();
+                    let chars: &mut Vec<char> = expansions.entry(key).or_default();
+                    if !chars.contains(&c) {
+                        chars.push(c);
+                    }
+                }
             }
         }
 
-        w!("}}");
-        out
+        let mut table = HashMap::new();
+        for class in classes.values().filter(|class| class.len() > 1) {
+            for &c in class {
+                table.insert(c, class.iter().cloned().filter(|&o| o != c).collect());
+            }
+        }
+        CaseFolding {
+            classes: table,
+            expansions,
+        }
+    })
+}
+
+// The representative of the case folding class of `c`
+fn class_key(c: char) -> char {
+    fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
     }
+
+    let upper = single(c.to_uppercase()).unwrap_or(c);
+    single(upper.to_lowercase()).unwrap_or(upper)
 }
 
-fn implode_ranges(bytes: BTreeSet<u8>) -> Vec<String> {
-    let mut res = Vec::new();
-    let mut i = bytes.iter();
-    if let Some(&start) = i.next() {
-        let mut start = start;
-        let mut prev = start;
-        for &cur in i {
-            if cur - 1 != prev {
-                res.push(format!("{:?}-{:?}", start as char, prev as char));
-                start = cur;
+impl CaseFolding {
+    fn class_of(&self, c: char) -> Vec<char> {
+        let mut class = vec![c];
+        class.extend(self.classes.get(&c).into_iter().flatten());
+        class
+    }
+
+    /// All strings that `c` should also match, including multi-char expansions with each of
+    ///  their chars replaced by its case variants.
+    fn variants(&self, c: char) -> BTreeSet<String> {
+        let mut variants: BTreeSet<String> = self.class_of(c)[1..]
+            .iter()
+            .map(|o| o.to_string())
+            .collect();
+        let upper: String = c.to_uppercase().collect();
+        let lower: String = c.to_lowercase().collect();
+        for expansion in [upper, lower] {
+            if expansion.chars().count() < 2 {
+                continue;
+            }
+            let mut partials = vec![String::new()];
+            for e in expansion.chars() {
+                partials = partials
+                    .iter()
+                    .flat_map(|p| {
+                        self.class_of(e)
+                            .into_iter()
+                            .map(move |o| format!("{}{}", p, o))
+                    })
+                    .collect();
             }
-            prev = cur;
+            variants.extend(partials);
         }
-        res
-    } else {
-        unreachable!();
+        variants
+    }
+
+    /// The chars, with their case variants, whose case mapping expands to `chars`, e.g. `ß`
+    ///  and `ẞ` for `ss`
+    fn contractions(&self, chars: &[char]) -> BTreeSet<char> {
+        let key: Vec<char> = chars.iter().cloned().map(class_key).collect();
+        self.expansions
+            .get(&key)
+            .into_iter()
+            .flatten()
+            .flat_map(|&c| self.class_of(c))
+            .collect()
     }
 }
 
@@ -357,17 +2248,24 @@ fn implode_ranges(bytes: BTreeSet<u8>) -> Vec<String> {
 pub struct DotOptions {
     pub bold_dict_edges: bool,
     pub suppress_stuck_state: bool,
+    /// Replaces the dictionary as the label of the graph
+    pub label: Option<String>,
+    /// Shows the pattern ends next to the final states
+    pub show_pattern_ends: bool,
+    /// Labels per pattern number, e.g. from `NFA::from_labeled_dictionary`, to show in the final
+    ///  states of `NFA::dot`
+    pub labels: Option<Vec<String>>,
 }
 
 /// Flips a map that represents a non-injective multivalued function
 ///  to a map that represents the inverse non-injective multivalued function
-fn flip_multimap<K: Ord + Clone, V: Ord>(
-    multimap: BTreeMap<K, BTreeSet<V>>,
+fn flip_multimap<K: Ord + Clone, V: Ord, S: IntoIterator<Item = V>>(
+    multimap: BTreeMap<K, S>,
 ) -> BTreeMap<V, BTreeSet<K>> {
-    let mut res = BTreeMap::new();
+    let mut res: BTreeMap<V, BTreeSet<K>> = BTreeMap::new();
     for (k, vs) in multimap {
         for v in vs {
-            res.entry(v).or_insert_with(BTreeSet::new).insert(k.clone());
+            res.entry(v).or_default().insert(k.clone());
         }
     }
     res
@@ -375,50 +2273,274 @@ fn flip_multimap<K: Ord + Clone, V: Ord>(
 
 impl Automaton<Input> for NFA {
     type State = BTreeSet<StateNumber>;
+    type Payload = PatternNumber;
 
     fn start_state(&self) -> Self::State {
-        [START].iter().cloned().collect()
+        self.epsilon_closure(&[START].iter().cloned().collect())
     }
 
     fn stuck_state(&self) -> Self::State {
         [STUCK].iter().cloned().collect()
     }
 
+    #[inline]
+    fn state_is_stuck(&self, states: &Self::State) -> bool {
+        states.iter().all(|&state| state == STUCK)
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        Some(self.longest_pattern_len().unwrap_or(0))
+    }
+
     #[inline]
     fn next_state(&self, states: &Self::State, input: &Input) -> Self::State {
         let mut nxt_states = BTreeSet::new();
+        let input = &self.fold(*input);
         for &state in states {
-            if let Some(states) = self.states[state].transitions.get(input) {
-                for &nxt_state in states {
-                    nxt_states.insert(nxt_state);
+            let mut state = state;
+            loop {
+                if let Some(states) = self.states[state].transitions.get(input) {
+                    for &nxt_state in states {
+                        nxt_states.insert(nxt_state);
+                    }
+                    break;
+                }
+                match &self.failure_links {
+                    Some(_) if state == START => {
+                        nxt_states.insert(START);
+                        break;
+                    }
+                    Some(failure_links) if state != STUCK => state = failure_links[state],
+                    _ => break,
                 }
             }
         }
-        nxt_states
+        self.close(nxt_states)
     }
 
     #[inline]
     fn has_match(&self, states: &Self::State, patt_no_offset: usize) -> bool {
-        for &state in states {
-            if patt_no_offset < self.states[state].pattern_ends.len() {
-                return true;
+        let states = self.closed(states);
+        if self.failure_links.is_none() {
+            let mut patt_no_offset = patt_no_offset;
+            for &state in states.iter() {
+                let ends = self.states[state].pattern_ends.len();
+                if patt_no_offset < ends {
+                    return true;
+                }
+                patt_no_offset -= ends;
             }
+            return false;
+        }
+        let matches: usize = states
+            .iter()
+            .flat_map(|&state| self.match_states(state))
+            .map(|s| self.states[s].pattern_ends.len())
+            .sum();
+        patt_no_offset < matches
+    }
+
+    #[inline]
+    fn state_is_final(&self, states: &Self::State) -> bool {
+        let states = self.closed(states);
+        if self.failure_links.is_none() {
+            return states.iter().any(|&state| self.states[state].is_final());
         }
-        false
+        states
+            .iter()
+            .any(|&state| self.match_states(state).any(|s| self.states[s].is_final()))
     }
 
     #[inline]
     fn get_match(&self, states: &Self::State, patt_no_offset: usize, text_offset: usize) -> Match {
-        for &state in states {
-            if let Some(&patt_no) = self.states[state].pattern_ends.get(patt_no_offset) {
-                return Match {
-                    patt_no,
-                    start: text_offset - self.dict[patt_no].len(),
-                    end: text_offset,
-                };
+        let states = self.closed(states);
+        let patt_no = if self.failure_links.is_none() {
+            let mut patt_no_offset = patt_no_offset;
+            let mut found = None;
+            for &state in states.iter() {
+                let ends = &self.states[state].pattern_ends;
+                if let Some(&patt_no) = ends.get(patt_no_offset) {
+                    found = Some(patt_no);
+                    break;
+                }
+                patt_no_offset -= ends.len();
+            }
+            found
+        } else {
+            states
+                .iter()
+                .flat_map(|&state| self.match_states(state))
+                .flat_map(|s| self.states[s].pattern_ends.iter().cloned())
+                .nth(patt_no_offset)
+        }
+        .expect("There is no match of this pattern!");
+        Match {
+            payload: patt_no,
+            start: text_offset - pattern_len(&self.dict, &self.dict_index, patt_no),
+            end: text_offset,
+        }
+    }
+}
+
+/// An `NFA` whose matches report a payload per pattern instead of the pattern number. See
+///  `NFA::from_dictionary_with_payloads`.
+#[derive(Debug)]
+pub struct NfaWithPayload<T> {
+    nfa: NFA,
+    payloads: Vec<T>,
+}
+
+impl<T> NfaWithPayload<T> {
+    pub fn nfa(&self) -> &NFA {
+        &self.nfa
+    }
+
+    /// For the transformations that keep the pattern numbers, like `ignore_prefixes`
+    pub fn nfa_mut(&mut self) -> &mut NFA {
+        &mut self.nfa
+    }
+
+    /// Replaces the automaton by `f(nfa)`, e.g. `|nfa| nfa.powerset_construction()`. The new
+    ///  automaton should report the same pattern numbers.
+    pub fn map_nfa<F: FnOnce(NFA) -> NFA>(self, f: F) -> Self {
+        NfaWithPayload {
+            nfa: f(self.nfa),
+            payloads: self.payloads,
+        }
+    }
+
+    pub fn payload(&self, patt_no: PatternNumber) -> &T {
+        &self.payloads[patt_no]
+    }
+}
+
+impl<T: Copy + fmt::Debug> Automaton<Input> for NfaWithPayload<T> {
+    type State = BTreeSet<StateNumber>;
+    type Payload = T;
+
+    fn start_state(&self) -> Self::State {
+        self.nfa.start_state()
+    }
+
+    fn stuck_state(&self) -> Self::State {
+        self.nfa.stuck_state()
+    }
+
+    #[inline]
+    fn state_is_stuck(&self, states: &Self::State) -> bool {
+        self.nfa.state_is_stuck(states)
+    }
+
+    fn max_match_len(&self) -> Option<usize> {
+        self.nfa.max_match_len()
+    }
+
+    #[inline]
+    fn next_state(&self, states: &Self::State, input: &Input) -> Self::State {
+        self.nfa.next_state(states, input)
+    }
+
+    #[inline]
+    fn has_match(&self, states: &Self::State, patt_no_offset: usize) -> bool {
+        self.nfa.has_match(states, patt_no_offset)
+    }
+
+    #[inline]
+    fn state_is_final(&self, states: &Self::State) -> bool {
+        self.nfa.state_is_final(states)
+    }
+
+    #[inline]
+    fn get_match(
+        &self,
+        states: &Self::State,
+        patt_no_offset: usize,
+        text_offset: usize,
+    ) -> Match<T> {
+        let m = self.nfa.get_match(states, patt_no_offset, text_offset);
+        Match {
+            payload: self.payloads[m.payload],
+            start: m.start,
+            end: m.end,
+        }
+    }
+}
+
+/// An iterator of the matches of an `NFA` that skips ahead with the prefilter bytes whenever
+///  the search is back in the start state. See `NFA::find_with_prefilter`.
+#[derive(Debug)]
+pub struct PrefilterMatches<'i, 'a> {
+    nfa: &'a NFA,
+    input: &'i [Input],
+    offset: usize,
+    state: BTreeSet<StateNumber>,
+    start: BTreeSet<StateNumber>,
+    prefilter: [bool; 256],
+    skip: bool,
+}
+
+impl<'i, 'a> Iterator for PrefilterMatches<'i, 'a> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        let mut offset = self.offset;
+        while offset < self.input.len() {
+            if self.skip && self.state == self.start {
+                let prefilter = &self.prefilter;
+                match self.input[offset..]
+                    .iter()
+                    .position(|&byte| prefilter[byte as usize])
+                {
+                    Some(skipped) => offset += skipped,
+                    None => break,
+                }
+            }
+            self.state = self.nfa.next_state(&self.state, &self.input[offset]);
+            offset += 1;
+            if self.nfa.has_match(&self.state, 0) {
+                self.offset = offset;
+                return Some(self.nfa.get_match(&self.state, 0, offset));
+            }
+        }
+        self.offset = self.input.len();
+        None
+    }
+}
+
+/// An iterator of the highest priority match at every position where a pattern ends. See
+///  `NFA::find_priority`.
+#[derive(Debug)]
+pub struct PriorityMatches<'i, 'a> {
+    nfa: &'a NFA,
+    input: &'i [Input],
+    offset: usize,
+    state: BTreeSet<StateNumber>,
+}
+
+impl<'i, 'a> Iterator for PriorityMatches<'i, 'a> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        while self.offset < self.input.len() {
+            self.state = self.nfa.next_state(&self.state, &self.input[self.offset]);
+            self.offset += 1;
+            let mut best: Option<Match> = None;
+            let mut patt_no_offset = 0;
+            while self.nfa.has_match(&self.state, patt_no_offset) {
+                let m = self.nfa.get_match(&self.state, patt_no_offset, self.offset);
+                let better = best.is_none_or(|best| {
+                    self.nfa.priority_of(m.payload) > self.nfa.priority_of(best.payload)
+                });
+                if better {
+                    best = Some(m);
+                }
+                patt_no_offset += 1;
+            }
+            if best.is_some() {
+                return best;
             }
         }
-        panic!("There is no match of this pattern!");
+        None
     }
 }
 
@@ -427,7 +2549,7 @@ impl fmt::Debug for NFA {
         macro_rules! w {
             ($($tt:tt)*) => { write!(f, $($tt)*)? }
         }
-        for (i, state) in (*self.states).into_iter().enumerate() {
+        for (i, state) in self.states.iter().enumerate() {
             w!("{}", i);
             if i == START {
                 w!(" (start)");
@@ -442,7 +2564,7 @@ impl fmt::Debug for NFA {
                 .transitions
                 .iter()
                 .map(|(&c, s)| (c as char, s.clone()))
-                .collect::<BTreeMap<char, BTreeSet<StateNumber>>>();
+                .collect::<BTreeMap<char, StateSet>>();
             w!("{:#?},\n", tr);
         }
         Ok(())
@@ -453,6 +2575,7 @@ impl NFAState {
     fn new() -> Self {
         NFAState {
             transitions: BTreeMap::new(),
+            epsilon: BTreeSet::new(),
             pattern_ends: Vec::new(),
         }
     }
@@ -461,8 +2584,11 @@ impl NFAState {
         !self.pattern_ends.is_empty()
     }
 
-    fn into_dfa(self) -> Result<DFAState, ()> {
-        let mut transitions = vec![STUCK; 256];
+    fn into_dfa(self, fold_table: &Option<[Input; 256]>) -> Result<DFAState, ()> {
+        if !self.epsilon.is_empty() {
+            return Err(());
+        }
+        let mut transitions = [STUCK; 256];
         for (&i, sns) in &self.transitions {
             if sns.len() != 1 {
                 return Err(());
@@ -472,18 +2598,18 @@ impl NFAState {
                 None => unreachable!(),
             }
         }
-        Ok(DFAState::new(
-            transitions.into_boxed_slice(),
-            self.pattern_ends,
-        ))
+        fold_row(fold_table, &mut transitions);
+        Ok(DFAState::new(transitions, self.pattern_ends))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::automaton::Searcher;
+    use crate::util::pseudo_random;
 
-    static BASIC_DICTIONARY: &'static [&'static str] = &["a", "ab", "bab", "bc", "bca", "c", "caa"];
+    static BASIC_DICTIONARY: &[&str] = &["a", "ab", "bab", "bc", "bca", "c", "caa"];
 
     #[test]
     fn basic() {
@@ -491,8 +2617,487 @@ mod tests {
         for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
             assert!(nfa.apply(word.as_bytes()).contains(&patt_no));
         }
-        assert!(nfa.apply("bbc".as_bytes()).is_empty());
-        assert!(nfa.apply("abb".as_bytes()).is_empty());
+        assert!(nfa.apply("bbc".as_bytes()).is_empty());
+        assert!(nfa.apply("abb".as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn prune_unreachable_states() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.add_depth_map();
+        let caa = nfa.states.len() - 1;
+        nfa.set_state_name(caa, "caa");
+        // Cut off the branch of the patterns that start with "b"
+        nfa.states[START].transitions.remove(&b'b');
+
+        nfa.prune_unreachable_states();
+        assert_eq!(nfa.state_count(), 7);
+        assert_eq!(nfa.state_name(6), Some("caa"));
+        let depth_states: usize = nfa.depth_map.values().map(|states| states.len()).sum();
+        assert_eq!(depth_states, nfa.state_count() - 1);
+        let original = NFA::from_dictionary(BASIC_DICTIONARY);
+        for input in &["a", "ab", "c", "caa", "x", "cb", ""] {
+            assert_eq!(
+                nfa.apply(input.as_bytes()),
+                original.apply(input.as_bytes())
+            );
+        }
+        for input in &["bab", "bc", "bca"] {
+            assert!(nfa.apply(input.as_bytes()).is_empty());
+        }
+    }
+
+    #[test]
+    fn find_at() {
+        let nfa = NFA::from_dictionary(["bca", "d"]);
+        let dnfa = {
+            let mut nfa = NFA::from_dictionary(["bca", "d"]);
+            nfa.ignore_prefixes();
+            nfa.powerset_construction()
+        };
+        let haystack = b"xdxxxxxbcaxxxxxxxxxx";
+        assert_eq!(haystack.len(), 20);
+        let matches: Vec<Match> = dnfa.find_at(haystack, 4).collect();
+        assert_eq!(
+            matches,
+            vec![Match {
+                payload: 0,
+                start: 7,
+                end: 10
+            }]
+        );
+        assert_eq!(
+            dnfa.find_iter_from(haystack, 4).collect::<Vec<_>>(),
+            matches
+        );
+        assert_eq!(dnfa.find_at(haystack, 0).count(), 2);
+        assert_eq!(
+            nfa.find_at(haystack, 7).next().map(|m| (m.start, m.end)),
+            Some((7, 10))
+        );
+    }
+
+    #[test]
+    fn reversed() {
+        let nfa = NFA::from_dictionary(["ab", "bc"]);
+        let reversed = nfa.reversed();
+        assert_eq!(reversed.apply(b"ba"), vec![0]);
+        assert_eq!(reversed.apply(b"cb"), vec![1]);
+        assert!(reversed.apply(b"ab").is_empty());
+        assert_eq!(reversed.alphabet, nfa.alphabet);
+
+        let mut nfa = NFA::from_dictionary_with_ids(["ab", "bc"], vec![7, 3]).unwrap();
+        nfa.ignore_prefixes();
+        let reversed = nfa.reversed();
+        assert_eq!(reversed.apply(b"cb"), vec![3]);
+        assert_eq!(reversed.alphabet.len(), 256);
+    }
+
+    #[test]
+    fn reversed_case_folding() {
+        let mut lowercase = [0; 256];
+        for (byte, canonical) in lowercase.iter_mut().enumerate() {
+            *canonical = (byte as u8).to_ascii_lowercase();
+        }
+        let nfa = NFA::from_dictionary_case_fold(["AB"], &lowercase);
+        let reversed = nfa.reversed();
+        assert_eq!(reversed.apply(b"BA"), vec![0]);
+        assert_eq!(reversed.apply(b"ba"), vec![0]);
+        assert_eq!(reversed.dict, vec![b"BA".to_vec()]);
+        assert_eq!(reversed.alphabet, nfa.alphabet);
+
+        let nfa = NFA::from_dictionary_nocase(["ab"]);
+        let reversed = nfa.reversed();
+        for input in &["ba", "BA", "bA", "Ba"] {
+            assert_eq!(reversed.apply(input.as_bytes()), vec![0]);
+        }
+        assert!(reversed.apply(b"ab").is_empty());
+        assert_eq!(reversed.alphabet, nfa.alphabet);
+    }
+
+    #[test]
+    fn reversed_lookback() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        let reversed = nfa.reversed();
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let haystack = b"xbcaxcaabab";
+        let lookback: Vec<Match> = dfa
+            .find(haystack)
+            .map(|m| {
+                let backwards: Vec<u8> = haystack[..m.end].iter().rev().cloned().collect();
+                let len = reversed
+                    .find_overlapping(&backwards)
+                    .find(|rm| rm.payload == m.payload)
+                    .expect("The reversed pattern matches")
+                    .end;
+                Match {
+                    payload: m.payload,
+                    start: m.end - len,
+                    end: m.end,
+                }
+            })
+            .collect();
+        assert_eq!(lookback, nfa.find(haystack).collect::<Vec<_>>());
+        assert_eq!(lookback.len(), 12);
+    }
+
+    #[test]
+    fn statistics() {
+        let nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        // stuck, start, a, ab, b, ba, bab, bc, bca, c, ca, caa
+        assert_eq!(nfa.state_count(), 12);
+        // A trie has a transition into every state but stuck and start
+        assert_eq!(nfa.transition_count(), 10);
+        assert_eq!(nfa.alphabet_size(), 3);
+        assert_eq!(nfa.dict_byte_count(), 15);
+
+        let mut nfa = nfa;
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.alphabet_size(), 256);
+    }
+
+    #[test]
+    fn from_alternation() {
+        let parts = vec![NFA::from_dictionary(["a"]), NFA::from_dictionary(["b"])];
+        let parts_states: usize = parts.iter().map(|part| part.state_count()).sum();
+        let nfa = NFA::from_alternation(parts).unwrap();
+        assert_eq!(nfa.apply(b"a"), vec![0]);
+        assert_eq!(nfa.apply(b"b"), vec![1]);
+        assert!(nfa.apply(b"ab").is_empty());
+        assert!(nfa.state_count() < parts_states);
+
+        let parts = vec![
+            NFA::from_dictionary(&BASIC_DICTIONARY[..3]),
+            NFA::from_dictionary_with_ids(&BASIC_DICTIONARY[3..], vec![10, 11, 12, 13]).unwrap(),
+        ];
+        let mut nfa = NFA::from_alternation(parts).unwrap();
+        assert_eq!(nfa.alphabet, vec![b'a', b'b', b'c']);
+        for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
+            assert_eq!(nfa.apply(word.as_bytes()), vec![patt_no]);
+        }
+        nfa.ignore_prefixes();
+        let mut expected = NFA::from_dictionary(BASIC_DICTIONARY);
+        expected.ignore_prefixes();
+        let haystack = b"xbcaxcaabab";
+        assert!(nfa.find(haystack).eq(expected.find(haystack)));
+        let dnfa = nfa.powerset_construction();
+        assert!(dnfa.find(haystack).eq(expected.find(haystack)));
+    }
+
+    #[test]
+    fn from_alternation_mixed_anchoring() {
+        let mut unanchored = NFA::from_dictionary(["a"]);
+        unanchored.ignore_prefixes();
+        let parts = vec![unanchored, NFA::from_dictionary(["b"])];
+        let nfa = NFA::from_alternation(parts).unwrap();
+        assert_eq!(nfa.apply(b"a"), vec![0]);
+        assert_eq!(nfa.apply(b"xa"), vec![0]);
+        assert_eq!(nfa.apply(b"b"), vec![1]);
+        assert!(nfa.apply(b"xb").is_empty());
+        assert!(nfa.apply(b"ab").is_empty());
+
+        let spans: Vec<_> = nfa
+            .find(b"bxab")
+            .map(|m| (m.payload, m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(1, 0, 1), (0, 2, 3)]);
+        let dnfa = nfa.powerset_construction();
+        for input in &["a", "xa", "b", "xb", "ab", "ba", ""] {
+            assert_eq!(dnfa.apply(input.as_bytes()), nfa.apply(input.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn from_alternation_priorities() {
+        let parts = vec![
+            NFA::from_dictionary(["a"]),
+            NFA::from_prioritized_dictionary(&[("b", 3), ("c", 5)]),
+        ];
+        let nfa = NFA::from_alternation(parts).unwrap();
+        assert_eq!(
+            (0..3)
+                .map(|patt_no| nfa.priority_of(patt_no))
+                .collect::<Vec<_>>(),
+            vec![0, 3, 5]
+        );
+    }
+
+    #[test]
+    fn from_alternation_case_folding() {
+        let mut fold = [0; 256];
+        for (byte, canonical) in fold.iter_mut().enumerate() {
+            *canonical = (byte as u8).to_ascii_lowercase();
+        }
+        let parts = vec![
+            NFA::from_dictionary(["a"]),
+            NFA::from_dictionary_case_fold(["b"], &fold),
+        ];
+        assert_eq!(
+            NFA::from_alternation(parts).err(),
+            Some(ConversionError::DifferentCaseFolding { part: 1 })
+        );
+    }
+
+    #[test]
+    fn add_pattern() {
+        let mut nfa = NFA::from_dictionary(["a"]);
+        assert_eq!(nfa.add_pattern(b"b"), Ok(1));
+        let expected = NFA::from_dictionary(["a", "b"]);
+        assert_eq!(nfa.alphabet, expected.alphabet);
+        for input in &["a", "b", "", "ab", "c", "ba"] {
+            assert_eq!(
+                nfa.apply(input.as_bytes()),
+                expected.apply(input.as_bytes())
+            );
+        }
+
+        let mut nfa = NFA::from_dictionary(&BASIC_DICTIONARY[..3]);
+        nfa.add_depth_map();
+        nfa.add_patterns(&BASIC_DICTIONARY[3..]).unwrap();
+        assert!(nfa.depth_map.is_empty());
+        assert_eq!(
+            nfa.state_count(),
+            NFA::from_dictionary(BASIC_DICTIONARY).state_count()
+        );
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+
+        let mut nfa = NFA::from_dictionary_with_ids(["ab"], vec![5]).unwrap();
+        assert_eq!(nfa.add_pattern(b"b"), Ok(6));
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+    }
+
+    #[test]
+    fn add_pattern_after_ignore_prefixes() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.add_pattern(b"xyz"), Err(ConversionError::NotATrie));
+        assert_eq!(nfa.dict.len(), BASIC_DICTIONARY.len());
+    }
+
+    #[test]
+    fn add_pattern_nocase() {
+        let mut nfa = NFA::from_dictionary_nocase(["ab"]);
+        assert_eq!(nfa.add_pattern(b"cD"), Ok(1));
+        assert_eq!(nfa.add_pattern(b"AX"), Ok(2));
+        let expected = NFA::from_dictionary_nocase(["ab", "cD", "AX"]);
+        assert_eq!(nfa.alphabet, expected.alphabet);
+        assert_eq!(nfa.state_count(), expected.state_count());
+        for input in &[
+            "ab", "AB", "cd", "CD", "cD", "ax", "Ax", "aX", "c", "abx", "",
+        ] {
+            assert_eq!(
+                nfa.apply(input.as_bytes()),
+                expected.apply(input.as_bytes())
+            );
+        }
+        assert_eq!(nfa.apply(b"CD"), vec![1]);
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+    }
+
+    #[test]
+    fn find_priority() {
+        let mut nfa = NFA::from_prioritized_dictionary(&[("b", 1), ("ab", 10)]);
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.priority_of(1), 10);
+        let found: Vec<Match> = nfa.find_priority(b"xab").collect();
+        assert_eq!(
+            found,
+            vec![Match {
+                payload: 1,
+                start: 1,
+                end: 3
+            }]
+        );
+        assert_eq!(nfa.find(b"xab").count(), 2);
+
+        let mut nfa = NFA::from_prioritized_dictionary(&[("b", 20), ("ab", 10)]);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        let found: Vec<_> = dnfa.find_priority(b"xab").map(|m| m.payload).collect();
+        assert_eq!(found, vec![0]);
+
+        // Patterns that end at different positions are all reported
+        let mut nfa = NFA::from_prioritized_dictionary(&[("a", 1), ("ab", 10)]);
+        nfa.ignore_prefixes();
+        let found: Vec<_> = nfa.find_priority(b"ab").map(|m| m.payload).collect();
+        assert_eq!(found, vec![0, 1]);
+        assert_eq!(NFA::from_dictionary(["a"]).priority_of(0), 0);
+    }
+
+    #[test]
+    fn minimize() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        nfa.ignore_postfixes();
+        let dnfa = nfa.powerset_construction();
+        let min = nfa.minimize();
+        assert!(min.states.len() < dnfa.states.len());
+        assert!(min
+            .states
+            .iter()
+            .all(|st| st.transitions.values().all(|to| to.len() == 1)));
+        for input in &["a", "bca", "xbcax", "cab", "abab", "bb", "xyz", ""] {
+            assert_eq!(min.apply(input.as_bytes()), dnfa.apply(input.as_bytes()));
+        }
+        let haystack = "xbabcaabcab bca caac".as_bytes();
+        assert!(min.find(haystack).eq(dnfa.find(haystack)));
+    }
+
+    #[test]
+    fn apply_mode() {
+        let mut nfa = NFA::from_dictionary(["c", "bc", "abc", "bc"]);
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.apply_mode(b"abc", MatchMode::All), vec![0, 1, 3, 2]);
+        assert_eq!(nfa.apply_mode(b"abc", MatchMode::All), nfa.apply(b"abc"));
+        assert_eq!(nfa.apply_mode(b"abc", MatchMode::First), vec![0]);
+        assert_eq!(nfa.apply_mode(b"abc", MatchMode::Longest), vec![2]);
+        assert_eq!(nfa.apply_mode(b"xbc", MatchMode::Longest), vec![1, 3]);
+        for &mode in &[MatchMode::First, MatchMode::All, MatchMode::Longest] {
+            assert!(nfa.apply_mode(b"abd", mode).is_empty());
+        }
+    }
+
+    #[test]
+    fn from_dictionary_nocase() {
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let mut nfa = NFA::from_dictionary(["Sherlock", "Street"]);
+        nfa.ignore_prefixes();
+        let config = NfaConfig {
+            case_insensitive: true,
+        };
+        let mut nocase = NFA::from_dictionary_with_config(["Sherlock", "Street"], config);
+        nocase.ignore_prefixes();
+        assert!(nocase.find(haystack).count() > nfa.find(haystack).count());
+        assert_eq!(nocase.apply(b"xSHERLOCK"), vec![0]);
+        assert_eq!(nocase.apply(b"xstReeT"), vec![1]);
+        assert_eq!(nocase.dict[0], b"Sherlock".to_vec());
+    }
+
+    #[test]
+    fn from_dictionary_nocase_non_ascii() {
+        // "Élan" and "été" in UTF-8, and a byte that is a letter after `^ 0x20`
+        let nfa =
+            NFA::from_dictionary_nocase([&b"\xc3\x89lan"[..], b"\xc3\xa9t\xc3\xa9", b"\xc1x"]);
+        assert_eq!(nfa.apply(b"\xc3\x89LAN"), vec![0]);
+        assert!(nfa.apply(b"\xc3\xa9lan").is_empty());
+        assert_eq!(nfa.apply(b"\xc3\xa9T\xc3\xa9"), vec![1]);
+        assert!(nfa.apply(b"\xe3\xa9t\xc3\xa9").is_empty());
+        assert!(nfa.apply(b"\xe1X").is_empty());
+        assert_eq!(nfa.apply(b"\xc1X"), vec![2]);
+        assert!(!nfa.alphabet.contains(&0xe3));
+    }
+
+    #[test]
+    fn epsilon_transitions() {
+        // A fresh start state with epsilon transitions to the start states of both NFAs
+        let mut joined = NFA::from_dictionary(Vec::<&str>::new());
+        let mut alphabet = BTreeSet::new();
+        for dict in [&["ab", "b"][..], &["bc", "abc"][..]] {
+            let nfa = NFA::from_dictionary(dict);
+            let offset = joined.states.len();
+            let patt_offset = joined.dict.len();
+            for mut state in nfa.states {
+                for to in state.transitions.values_mut() {
+                    *to = to.iter().map(|&s| s + offset).collect();
+                }
+                for patt_no in &mut state.pattern_ends {
+                    *patt_no += patt_offset;
+                }
+                joined.states.push(state);
+            }
+            joined.dict.extend(nfa.dict);
+            alphabet.extend(nfa.alphabet);
+            joined.add_epsilon_transition(START, offset + START);
+        }
+        joined.alphabet = alphabet.into_iter().collect();
+
+        let start = joined.start_state();
+        assert_eq!(start.len(), 3);
+        assert_eq!(joined.epsilon_closure(&start), start);
+        assert_eq!(joined.apply(b"ab"), vec![0]);
+        assert_eq!(joined.apply(b"b"), vec![1]);
+        assert_eq!(joined.apply(b"bc"), vec![2]);
+        assert_eq!(joined.apply(b"abc"), vec![3]);
+        assert!(joined.apply(b"a").is_empty());
+        assert!(joined.apply(b"c").is_empty());
+        for dnfa in [
+            joined.powerset_construction_small(),
+            joined.powerset_construction_general(),
+        ] {
+            for input in &["ab", "b", "bc", "abc", "a", "c", "abcd"] {
+                assert_eq!(dnfa.apply(input.as_bytes()), joined.apply(input.as_bytes()));
+            }
+        }
+        assert!(joined.into_dfa().is_err());
+    }
+
+    #[test]
+    fn from_dictionary_with_payloads() {
+        let nfa =
+            NFA::from_dictionary_with_payloads(["dog", "run", "cat"], vec!["noun", "verb", "noun"])
+                .unwrap()
+                .map_nfa(|mut nfa| {
+                    nfa.ignore_prefixes();
+                    nfa.powerset_construction()
+                });
+        let payloads: Vec<&str> = nfa
+            .find(b"the cat and dog run")
+            .map(|m| m.payload)
+            .collect();
+        assert_eq!(payloads, vec!["noun", "noun", "verb"]);
+        let m = nfa.find(b"xrun").next().unwrap();
+        assert_eq!((m.payload, m.start, m.end), ("verb", 1, 4));
+        assert_eq!(*nfa.payload(1), "verb");
+
+        assert_eq!(
+            NFA::from_dictionary_with_payloads(["a", "b"], vec![1]).unwrap_err(),
+            DictError::IdsTooShort {
+                ids: 1,
+                patterns: 2
+            }
+        );
+    }
+
+    #[test]
+    fn binary_patterns() {
+        let mut nfa = NFA::from_dictionary([&b"\x00\x01"[..], &b"\xff\xfe"[..]]);
+        assert_eq!(nfa.apply(b"\x00\x01"), vec![0]);
+        assert_eq!(nfa.apply(b"\xff\xfe"), vec![1]);
+        assert!(nfa.apply(b"\x00").is_empty());
+        nfa.ignore_prefixes();
+        let expected = Match {
+            payload: 1,
+            start: 1,
+            end: 3,
+        };
+        assert_eq!(nfa.find(b"x\xff\xfey").collect::<Vec<_>>(), vec![expected]);
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        assert_eq!(dfa.find(b"x\xff\xfey").collect::<Vec<_>>(), vec![expected]);
+    }
+
+    #[test]
+    fn every_byte_value() {
+        let dict: Vec<[u8; 2]> = (0..=255).map(|byte| [byte, 255 - byte]).collect();
+        let nfa = NFA::from_dictionary(&dict);
+        assert_eq!(nfa.alphabet.len(), 256);
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+        let dfa = nfa.into_dfa().unwrap();
+        for (patt_no, pattern) in dict.iter().enumerate() {
+            assert_eq!(dfa.apply(pattern), vec![patt_no]);
+        }
+    }
+
+    #[test]
+    fn shared_prefixes() {
+        let sorted = ["", "ab", "abc", "abd", "b", "ba", "ba"];
+        let unsorted = ["abd", "b", "", "ba", "abc", "ba", "ab"];
+        for dict in &[sorted, unsorted] {
+            let nfa = NFA::from_dictionary(dict);
+            // stuck, start, a, ab, abc, abd, b, ba
+            assert_eq!(nfa.states.len(), 8);
+            assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+        }
     }
 
     #[test]
@@ -551,6 +3156,21 @@ mod tests {
         assert!(nfa.apply("abb".as_bytes()).is_empty());
     }
 
+    #[test]
+    fn powerset_small_same_as_general() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        nfa.ignore_postfixes();
+        assert!(nfa.states.len() <= 64);
+        let small = nfa.powerset_construction_small();
+        let general = nfa.powerset_construction_general();
+        assert_eq!(small.states.len(), general.states.len());
+        for (s, g) in small.states.iter().zip(&general.states) {
+            assert_eq!(s.transitions, g.transitions);
+            assert_eq!(s.pattern_ends, g.pattern_ends);
+        }
+    }
+
     #[test]
     fn basic_powerset_ignore_prefixes() {
         let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY).powerset_construction();
@@ -597,11 +3217,357 @@ mod tests {
         assert!(!nfa.apply("abb".as_bytes()).is_empty());
     }
 
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn from_dictionary_parallel() {
+        let dict = ["", "a", "ab", "bab", "bc", "bca", "c", "caa", "cab", "zzz"];
+        let serial = NFA::from_dictionary(dict);
+        let parallel = NFA::from_dictionary_parallel(dict);
+        assert_eq!(serial.states.len(), parallel.states.len());
+        assert_eq!(serial.alphabet, parallel.alphabet);
+        for word in &[
+            "", "a", "ab", "ba", "bab", "bca", "cab", "caa", "zz", "zzz", "zzzz",
+        ] {
+            assert_eq!(
+                serial.apply(word.as_bytes()),
+                parallel.apply(word.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn reachable_states() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        let reachable = nfa.reachable_states();
+        assert_eq!(reachable[0], START);
+        assert_eq!(reachable.len(), nfa.states.len() - 1);
+        assert!(!reachable.contains(&STUCK));
+
+        nfa.states.push(NFAState::new());
+        assert_eq!(nfa.reachable_states(), reachable);
+
+        // Only reached by an epsilon transition, which all the traversals follow
+        let extra = nfa.states.len() - 1;
+        nfa.add_epsilon_transition(START, extra);
+        assert_eq!(nfa.reachable_states().len(), reachable.len() + 1);
+        assert!(nfa.reachable_states().contains(&extra));
+        assert!(nfa.depth_first_order().contains(&extra));
+        let state_count = nfa.state_count();
+        nfa.prune_unreachable_states();
+        assert_eq!(nfa.state_count(), state_count);
+    }
+
+    #[test]
+    fn pattern_lengths() {
+        let nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        assert_eq!(nfa.shortest_pattern_len(), Some(1));
+        assert_eq!(nfa.longest_pattern_len(), Some(3));
+        // 1 + 2 + 3 + 2 + 3 + 1 + 3 = 15 bytes
+        assert!((nfa.average_pattern_len() - 15.0 / 7.0).abs() < 1e-9);
+
+        let empty = NFA::from_dictionary(Vec::<&str>::new());
+        assert_eq!(empty.shortest_pattern_len(), None);
+        assert_eq!(empty.longest_pattern_len(), None);
+        assert_eq!(empty.average_pattern_len(), 0.0);
+    }
+
+    #[test]
+    fn depth_and_breadth_first_order() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        // start, a, ab, b, ba, bab, bc, bca, c, ca, caa
+        assert_eq!(nfa.depth_first_order(), (1..12).collect::<Vec<_>>());
+        assert_eq!(
+            nfa.breadth_first_order(),
+            vec![1, 2, 4, 9, 3, 5, 7, 10, 6, 8, 11]
+        );
+
+        nfa.ignore_prefixes();
+        nfa.ignore_postfixes();
+        for order in &[nfa.depth_first_order(), nfa.breadth_first_order()] {
+            assert_eq!(order[0], START);
+            let unique: BTreeSet<StateNumber> = order.iter().cloned().collect();
+            assert_eq!(unique.len(), order.len());
+            assert_eq!(order.len(), nfa.states.len() - 1);
+        }
+    }
+
+    #[test]
+    fn transitions_matrix() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes();
+        let matrix = nfa.transitions_matrix();
+        assert_eq!(matrix.len(), nfa.states.len());
+        assert!(matrix.iter().all(|row| row.len() == 256));
+        for word in &["a", "bab", "bca", "bbc", "abb", "xcaa"] {
+            let mut states: BTreeSet<StateNumber> = [START].iter().cloned().collect();
+            for &byte in word.as_bytes() {
+                states = states
+                    .iter()
+                    .flat_map(|&s| matrix[s][byte as usize].clone())
+                    .collect();
+            }
+            let patts: Vec<PatternNumber> = states
+                .iter()
+                .flat_map(|&s| nfa.states[s].pattern_ends.clone())
+                .collect();
+            assert_eq!(patts, nfa.apply(word.as_bytes()));
+        }
+
+        let dnfa = nfa.powerset_construction();
+        let matrix = dnfa.transitions_matrix_single();
+        for word in &["a", "bab", "bca", "bbc", "abb", "xcaa"] {
+            let mut state = Some(START);
+            for &byte in word.as_bytes() {
+                state = state.and_then(|s| matrix[s][byte as usize]);
+            }
+            let patts = state.map_or_else(Vec::new, |s| dnfa.states[s].pattern_ends.clone());
+            assert_eq!(patts, dnfa.apply(word.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn pretty_print() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.add_depth_map();
+        let out = nfa.pretty_print();
+        assert!(out.contains("START"));
+        assert!(out.contains("STUCK"));
+        let start_row = out.lines().find(|l| l.contains("START")).unwrap();
+        assert!(start_row.contains("'a' -> {2}, 'b' -> {4}, 'c' -> {9}"));
+        assert!(out
+            .lines()
+            .any(|l| l.starts_with("    3") && l.contains('*')));
+
+        let alphabet: Vec<String> = (b'a'..=b'z').map(|b| (b as char).to_string()).collect();
+        let out = NFA::from_dictionary(alphabet).pretty_print();
+        assert!(out.lines().all(|l| l.chars().count() <= 120));
+        assert!(out.contains("..."));
+    }
+
+    #[test]
+    fn state_names() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.set_state_name(START, "initial");
+        nfa.set_state_name(3, "after \"ab\"");
+        assert_eq!(nfa.state_name(START), Some("initial"));
+        assert_eq!(nfa.state_name(3), Some("after \"ab\""));
+        assert_eq!(nfa.state_name(2), None);
+        assert_eq!(nfa.state_name(100), None);
+
+        let dot = nfa.dot(DotOptions::default());
+        assert!(dot.contains(r#"    1 [label="initial"];"#));
+        assert!(dot.contains(r#"    3 [label="after \"ab\""] [peripheries=2];"#));
+        assert!(dot.contains("    2 [peripheries=2];"));
+
+        let out = nfa.pretty_print();
+        assert!(out.lines().any(|l| l.starts_with("    1 START initial")));
+        assert!(out.lines().any(|l| l.starts_with("    3 after \"ab\"")));
+    }
+
+    #[test]
+    fn labeled_dictionary() {
+        let entries: Vec<(&str, &str)> = BASIC_DICTIONARY
+            .iter()
+            .map(|&word| {
+                (
+                    word,
+                    if word.len() == 1 {
+                        "short"
+                    } else {
+                        "long \"word\""
+                    },
+                )
+            })
+            .collect();
+        let (nfa, labels) = NFA::from_labeled_dictionary(&entries);
+        assert_eq!(labels.len(), BASIC_DICTIONARY.len());
+        for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
+            assert_eq!(nfa.apply(word.as_bytes()), vec![patt_no]);
+        }
+
+        let dot = nfa.dot(DotOptions {
+            labels: Some(labels),
+            ..DotOptions::default()
+        });
+        assert!(dot.contains(r#"    2 [label="2: short"] [peripheries=2];"#));
+        assert!(dot.contains(r#"    3 [label="3: long \"word\""] [peripheries=2];"#));
+        assert!(dot.contains("    4;"));
+    }
+
+    #[test]
+    fn verify_dict_completeness() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+        nfa.ignore_prefixes();
+        nfa.ignore_postfixes();
+        assert_eq!(
+            nfa.powerset_construction().verify_dict_completeness(),
+            Ok(())
+        );
+
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.states[3].pattern_ends.clear();
+        assert_eq!(nfa.verify_dict_completeness(), Err(vec![1]));
+    }
+
+    #[test]
+    fn from_dictionary_safe() {
+        // Not `Clone`
+        let dict = BASIC_DICTIONARY.iter().map(|word| word.to_string());
+        let nfa = NFA::from_dictionary_safe(dict).unwrap();
+        for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
+            assert!(nfa.apply(word.as_bytes()).contains(&patt_no));
+        }
+    }
+
+    #[test]
+    fn from_dictionary_case_fold() {
+        let mut fold = [0; 256];
+        for (byte, canonical) in fold.iter_mut().enumerate() {
+            *canonical = (byte as u8).to_ascii_lowercase();
+        }
+        let mut nfa = NFA::from_dictionary_case_fold(["Sherlock", "street"], &fold);
+        assert_eq!(nfa.apply(b"SHERLOCK"), vec![0]);
+        assert_eq!(nfa.apply(b"sTreet"), vec![1]);
+        assert!(nfa.apply(b"sherlocks").is_empty());
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+
+        nfa.ignore_prefixes();
+        let mut lowercase = NFA::from_dictionary(["sherlock", "street"]);
+        lowercase.ignore_prefixes();
+        let lowercase = lowercase.powerset_construction().into_dfa().unwrap();
+        let expected = lowercase
+            .find(HAYSTACK_SHERLOCK.to_ascii_lowercase().as_bytes())
+            .count();
+        assert!(expected > 158);
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        assert_eq!(nfa.find(haystack).count(), expected);
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        assert_eq!(dfa.find(haystack).count(), expected);
+    }
+
+    #[test]
+    fn from_dictionary_validated() {
+        let nfa = NFA::from_dictionary_validated(BASIC_DICTIONARY, |_, _| Ok(())).unwrap();
+        for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
+            assert!(nfa.apply(word.as_bytes()).contains(&patt_no));
+        }
+
+        let res = NFA::from_dictionary_validated(BASIC_DICTIONARY, |_, bytes| {
+            if bytes.len() > 2 {
+                Err(format!("{} bytes is too long", bytes.len()))
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(
+            res.err(),
+            Some(DictError::CustomValidationFailed {
+                pattern_no: 2,
+                message: "3 bytes is too long".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn from_dictionary_with_limit() {
+        let nfa = NFA::from_dictionary_with_limit(BASIC_DICTIONARY, 3).unwrap();
+        for (patt_no, &word) in BASIC_DICTIONARY.iter().enumerate() {
+            assert!(nfa.apply(word.as_bytes()).contains(&patt_no));
+        }
+
+        assert_eq!(
+            NFA::from_dictionary_with_limit(BASIC_DICTIONARY, 2).err(),
+            Some(DictError::PatternTooLong {
+                pattern_no: 2,
+                length: 3,
+                max: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn from_dictionary_with_ids() {
+        let ids = vec![100, 42, 7];
+        let mut nfa = NFA::from_dictionary_with_ids(["ab", "bc", "cd"], ids).unwrap();
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.verify_dict_completeness(), Ok(()));
+        let nfa_matches: Vec<Match> = nfa.find(b"xabcd").collect();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let dfa_matches: Vec<Match> = dfa.find(b"xabcd").collect();
+        let expected = vec![
+            Match {
+                payload: 100,
+                start: 1,
+                end: 3,
+            },
+            Match {
+                payload: 42,
+                start: 2,
+                end: 4,
+            },
+            Match {
+                payload: 7,
+                start: 3,
+                end: 5,
+            },
+        ];
+        assert_eq!(nfa_matches, expected);
+        assert_eq!(dfa_matches, expected);
+
+        assert_eq!(
+            NFA::from_dictionary_with_ids(["ab", "b", "bc"], vec![1, 2]).err(),
+            Some(DictError::IdsTooShort {
+                ids: 2,
+                patterns: 3,
+            })
+        );
+        assert_eq!(
+            NFA::from_dictionary_with_ids(["ab", "b", "bc"], vec![1, 2, 1]).err(),
+            Some(DictError::DuplicateId {
+                id: 1,
+                first: 0,
+                second: 2,
+            })
+        );
+        // Only the ids of the patterns have to be distinct
+        assert!(NFA::from_dictionary_with_ids(["ab"], vec![1, 1]).is_ok());
+    }
+
+    #[test]
+    fn case_fold_unicode() {
+        let mut nfa = NFA::from_dictionary(["ärger", "straße", "Ωmega", "strasse"]);
+        nfa.case_fold_unicode().unwrap();
+        assert!(nfa.apply("ÄRGER".as_bytes()).contains(&0));
+        assert!(nfa.apply("äRgEr".as_bytes()).contains(&0));
+        assert!(nfa.apply("STRASSE".as_bytes()).contains(&1));
+        assert!(nfa.apply("strasse".as_bytes()).contains(&1));
+        assert!(nfa.apply("STRAẞE".as_bytes()).contains(&1));
+        assert!(nfa.apply("ωMEGA".as_bytes()).contains(&2));
+        assert!(nfa.apply("strase".as_bytes()).is_empty());
+        assert!(nfa.apply("arger".as_bytes()).is_empty());
+
+        // The other direction: `ss` matches `ß`
+        assert!(nfa.apply("straße".as_bytes()).contains(&3));
+        assert!(nfa.apply("STRAẞE".as_bytes()).contains(&3));
+        assert!(nfa.apply("STRASSE".as_bytes()).contains(&3));
+
+        let dnfa = nfa.powerset_construction();
+        assert!(dnfa.apply("STRASSE".as_bytes()).contains(&1));
+        assert!(dnfa.apply("ÄRGER".as_bytes()).contains(&0));
+        assert!(dnfa.apply("Straße".as_bytes()).contains(&3));
+
+        assert_eq!(nfa.case_fold_unicode(), Err(ConversionError::NotATrie));
+        let mut nfa = NFA::from_dictionary(["straße"]);
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.case_fold_unicode(), Err(ConversionError::NotATrie));
+    }
+
     use crate::automaton::Automaton;
     use std::iter;
 
     fn haystack_same(letter: char) -> String {
-        iter::repeat(letter).take(10000).collect()
+        iter::repeat_n(letter, 10000).collect()
     }
 
     #[test]
@@ -616,7 +3582,442 @@ mod tests {
         assert!(Automaton::find(dnfa, haystack.as_bytes()).next().is_none());
     }
 
-    static HAYSTACK_SHERLOCK: &'static str = include_str!("../benches/sherlock.txt");
+    static HAYSTACK_SHERLOCK: &str = include_str!("../benches/sherlock.txt");
+
+    #[test]
+    fn state_is_final() {
+        let nfa = NFA::from_dictionary(["", "ab"]);
+        let start = nfa.start_state();
+        assert!(nfa.state_is_final(&start));
+        let a = nfa.next_state(&start, &b'a');
+        assert!(!nfa.state_is_final(&a));
+        assert!(nfa.state_is_final(&nfa.next_state(&a, &b'b')));
+
+        let dfa = nfa.into_dfa().unwrap();
+        assert!(dfa.state_is_final(&dfa.start_state()));
+        let ddfa = dfa.into_ddfa().unwrap();
+        assert!(ddfa.state_is_final(&ddfa.start_state()));
+        assert!(!ddfa.state_is_final(&ddfa.stuck_state()));
+
+        // The final states of a complement have no pattern ends
+        let dfa = NFA::from_dictionary(["ab"])
+            .into_dfa()
+            .unwrap()
+            .complement();
+        assert!(dfa.state_is_final(&dfa.start_state()));
+        assert!(dfa.state_is_final(&dfa.stuck_state()));
+        let safe_ddfa = crate::dfa::DfaBuilder::from_dfa(&dfa)
+            .build()
+            .unwrap()
+            .into_safe_ddfa();
+        assert!(safe_ddfa.state_is_final(&safe_ddfa.start_state()));
+        assert!(!safe_ddfa.state_is_final(&3));
+        let ddfa = dfa.into_ddfa().unwrap();
+        assert!(ddfa.state_is_final(&ddfa.start_state()));
+        let ab = ddfa.next_state(&ddfa.next_state(&ddfa.start_state(), &b'a'), &b'b');
+        assert!(!ddfa.state_is_final(&ab));
+    }
+
+    #[test]
+    fn find_leftmost_longest() {
+        let spans = |matches: Vec<Match>| -> Vec<(usize, usize, usize)> {
+            matches
+                .iter()
+                .map(|m| (m.payload, m.start, m.end))
+                .collect()
+        };
+        let nfa = NFA::from_dictionary(["ab", "abc"]);
+        assert_eq!(nfa.find(b"abcd").next().map(|m| m.payload), Some(0));
+        let matches = nfa.find_leftmost_longest(b"abcd").collect();
+        assert_eq!(spans(matches), vec![(1, 0, 3)]);
+
+        let mut nfa = NFA::from_dictionary(["ab", "abc", "bcab", "c"]);
+        nfa.ignore_prefixes();
+        let expected = vec![(1, 1, 4), (0, 4, 6), (3, 7, 8)];
+        let matches = nfa.find_leftmost_longest(b"xabcabxc").collect();
+        assert_eq!(spans(matches), expected);
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let matches = dfa.find_leftmost_longest(b"xabcabxc").collect();
+        assert_eq!(spans(matches), expected);
+    }
+
+    #[test]
+    fn find_all_patterns_per_position() {
+        let mut nfa = NFA::from_dictionary(["a", "ab"]);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        let found: Vec<_> = dnfa.find(b"ab").map(|m| m.payload).collect();
+        assert_eq!(found, vec![0, 1]);
+
+        // Both end at the same position
+        let mut nfa = NFA::from_dictionary(["ab", "b"]);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        let spans: Vec<_> = dnfa
+            .find(b"xabx")
+            .map(|m| (m.payload, m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(0, 1, 3), (1, 2, 3)]);
+        assert!(nfa.find(b"xabx").eq(dnfa.find(b"xabx")));
+        let mut matches = dnfa.find(b"xabx");
+        assert_eq!(matches.by_ref().count(), 2);
+        matches.reset();
+        assert_eq!(matches.count(), 2);
+    }
+
+    #[test]
+    fn find_overlapping() {
+        let mut nfa = NFA::from_dictionary(["aa"]);
+        nfa.ignore_prefixes();
+        let spans: Vec<_> = nfa
+            .find_overlapping(b"aaa")
+            .map(|m| (m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(0, 2), (1, 3)]);
+
+        // Anchored, so `find` only looks at the start of the haystack
+        let anchored = NFA::from_dictionary(["aa"]);
+        let spans: Vec<_> = anchored
+            .find_overlapping(b"aaa")
+            .map(|m| (m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(0, 2), (1, 3)]);
+        assert_eq!(anchored.find(b"aaa").count(), 1);
+        let dfa = anchored.into_dfa().unwrap();
+        let spans: Vec<_> = dfa
+            .find_overlapping(b"aaa")
+            .map(|m| (m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(0, 2), (1, 3)]);
+        let spans: Vec<_> = NFA::from_dictionary(["a", "aa"])
+            .find_overlapping(b"aaa")
+            .map(|m| (m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(0, 1), (0, 2), (1, 2), (1, 3), (2, 3)]);
+
+        // Without a bound on how far to scan from each position, this would take quadratic
+        //  time for the automata that don't get stuck
+        let dict = ["Sherlock", "lock", "Holmes", "olm"];
+        let mut nfa = NFA::from_dictionary(dict);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let count = dfa.find(haystack).count();
+        assert_eq!(dfa.find_overlapping(haystack).count(), count);
+
+        // The anchored automata find the matches from every position as well
+        let anchored = NFA::from_dictionary(dict).into_dfa().unwrap();
+        assert_eq!(anchored.find_overlapping(haystack).count(), count);
+        assert!(anchored.find(haystack).count() < count);
+
+        let prefix = &haystack[..20_000];
+        let expected: Vec<Match> = anchored.find_overlapping(prefix).collect();
+        assert!(expected.len() >= dfa.find(prefix).count());
+        assert!(nfa.find_overlapping(prefix).eq(expected.iter().cloned()));
+        assert!(dnfa.find_overlapping(prefix).eq(expected.iter().cloned()));
+        let anchored = NFA::from_dictionary(dict);
+        assert!(anchored
+            .find_overlapping(prefix)
+            .eq(expected.iter().cloned()));
+    }
+
+    #[test]
+    fn labeled_dictionary_find() {
+        let (mut nfa, labels) =
+            NFA::from_labeled_dictionary(&[("Sherlock", "name"), ("Street", "place")]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let mut count = 0;
+        for m in dfa.find(haystack) {
+            let expected = match &haystack[m.start..m.end] {
+                b"Sherlock" => "name",
+                b"Street" => "place",
+                _ => panic!("Not a pattern"),
+            };
+            assert_eq!(NFA::label_of(&labels, &m), expected);
+            count += 1;
+        }
+        assert_eq!(count, 158);
+    }
+
+    #[test]
+    fn searcher() {
+        let mut nfa = NFA::from_dictionary(["Sherlock", "Street"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let expected: Vec<Match> = dfa.find(haystack).collect();
+
+        // Chunks of varying sizes around 64 bytes
+        let mut searcher = Searcher::from(&dfa);
+        let mut matches = Vec::new();
+        let mut rest = haystack;
+        let mut size = 64;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(size.min(rest.len()));
+            matches.extend(searcher.feed(chunk));
+            rest = tail;
+            size = (size * 7 + 13) % 97 + 16;
+        }
+        assert_eq!(matches.len(), 158);
+        assert_eq!(matches, expected);
+
+        let mut searcher = Searcher::new(&nfa);
+        assert_eq!(searcher.feed(b"xx Sher"), vec![]);
+        let m = searcher.feed(b"lock x");
+        assert_eq!(m.len(), 1);
+        assert_eq!((m[0].payload, m[0].start, m[0].end), (0, 3, 11));
+    }
+
+    #[test]
+    fn streaming_coincident_ends() {
+        let mut nfa = NFA::from_dictionary(["ab", "b"]);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let expected: Vec<Match> = dfa.find(b"xab").collect();
+        assert_eq!(expected.len(), 2);
+
+        let mut searcher = Searcher::new(&dfa);
+        let mut matches = searcher.feed(b"xa");
+        matches.extend(searcher.feed(b"b"));
+        assert_eq!(matches, expected);
+
+        let ends: Vec<(PatternNumber, usize)> = dfa
+            .find_from_iter(b"xab".iter().cloned())
+            .map(|m| (m.payload, m.end))
+            .collect();
+        let expected: Vec<(PatternNumber, usize)> =
+            expected.iter().map(|m| (m.payload, m.end)).collect();
+        assert_eq!(ends, expected);
+    }
+
+    #[test]
+    fn find_leftmost() {
+        let mut nfa = NFA::from_dictionary(["a", "ab"]);
+        nfa.ignore_prefixes();
+        let dnfa = nfa.powerset_construction();
+        let matches: Vec<Match> = dnfa.find_leftmost(b"ab").collect();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            (matches[0].payload, matches[0].start, matches[0].end),
+            (1, 0, 2)
+        );
+        assert_eq!(dnfa.find(b"ab").next().unwrap().payload, 0);
+
+        // The anchored automaton gets stuck, so only scans as far as a pattern goes
+        let dict = ["Sher", "Sherlock", "Holm", "Holmes"];
+        let anchored = NFA::from_dictionary(dict).into_dfa().unwrap();
+        let mut nfa = NFA::from_dictionary(dict);
+        nfa.ignore_prefixes();
+        let dfa = nfa.powerset_construction().into_dfa().unwrap();
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let leftmost: Vec<Match> = anchored.find_leftmost(haystack).collect();
+        assert!(leftmost.len() < dfa.find(haystack).count());
+        assert!(leftmost.iter().eq(dfa
+            .find_leftmost_longest(haystack)
+            .collect::<Vec<_>>()
+            .iter()));
+        assert!(leftmost
+            .iter()
+            .all(|m| m.payload == 1 || m.payload == 3 || m.end - m.start == 4));
+
+        // The automaton after ignore_prefixes never gets stuck, so only the bound of the longest
+        //  pattern keeps this from rescanning the rest of the haystack from every position
+        assert!(dfa.find_leftmost(haystack).eq(leftmost.iter().cloned()));
+        let safe = dfa.into_safe_ddfa();
+        assert!(safe.find_leftmost(haystack).eq(leftmost.iter().cloned()));
+    }
+
+    #[test]
+    fn find_earliest() {
+        let mut nfa = NFA::from_dictionary(["ab", "b"]);
+        nfa.ignore_prefixes();
+        let m = nfa.find_earliest("ab".as_bytes()).unwrap();
+        assert_eq!((m.payload, m.start, m.end), (0, 0, 2));
+
+        // "bcd" ends after "c", but starts before it
+        let mut nfa = NFA::from_dictionary(["c", "bcd"]);
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.find("abcde".as_bytes()).next().unwrap().payload, 0);
+        let m = nfa.find_earliest("abcde".as_bytes()).unwrap();
+        assert_eq!((m.payload, m.start, m.end), (1, 1, 4));
+        let m = nfa
+            .powerset_construction()
+            .find_earliest("abcde".as_bytes());
+        assert_eq!(m.map(|m| m.payload), Some(1));
+
+        assert_eq!(nfa.find_earliest("xyz".as_bytes()), None);
+    }
+
+    #[test]
+    fn ignore_prefixes_compact() {
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.ignore_prefixes_compact();
+        assert_eq!(nfa.alphabet, b"abc".to_vec());
+        assert_eq!(nfa.find(b"ccbab").count(), 5);
+        // A byte outside of the alphabet gets the search stuck
+        assert_eq!(nfa.find(b"xbab").count(), 0);
+
+        let compact = nfa.powerset_construction();
+        let mut full = NFA::from_dictionary(BASIC_DICTIONARY);
+        full.ignore_prefixes();
+        let full = full.powerset_construction();
+        assert_eq!(compact.find(b"ccbab").count(), full.find(b"ccbab").count());
+        let transitions =
+            |nfa: &NFA| -> usize { nfa.states.iter().map(|st| st.transitions.len()).sum() };
+        assert_eq!(compact.states.len(), full.states.len());
+        assert!(transitions(&compact) < transitions(&full));
+    }
+
+    #[test]
+    fn find_with_prefilter() {
+        let mut nfa = NFA::from_dictionary(["Sherlock", "Street"]);
+        let prefilter = nfa.prefilter_bytes();
+        assert_eq!(
+            (0..=255).filter(|&b| prefilter[b]).collect::<Vec<_>>(),
+            vec![b'S' as usize]
+        );
+        nfa.ignore_prefixes();
+        assert_eq!(nfa.prefilter_bytes(), prefilter);
+
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let expected: Vec<Match> = nfa.find(haystack).collect();
+        assert_eq!(expected.len(), 158);
+        assert_eq!(
+            nfa.find_with_prefilter(haystack).collect::<Vec<_>>(),
+            expected
+        );
+
+        let haystack = "z".repeat(1000);
+        assert_eq!(nfa.find_with_prefilter(haystack.as_bytes()).next(), None);
+
+        // Without ignore_prefixes nothing may be skipped
+        let nfa = NFA::from_dictionary(["ab", "b"]);
+        assert_eq!(nfa.find_with_prefilter(b"xab").next(), None);
+        assert_eq!(nfa.find_with_prefilter(b"ab").count(), 1);
+    }
+
+    #[test]
+    fn failure_links() {
+        let haystack = "xbabcaabcab bca caac";
+
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.build_failure_links();
+        assert_eq!(nfa.failure_links.as_ref().unwrap()[START], START);
+
+        let mut dnfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        dnfa.ignore_prefixes();
+        let dnfa = dnfa.powerset_construction();
+
+        let ends: Vec<usize> = nfa.find(haystack.as_bytes()).map(|m| m.end).collect();
+        let dnfa_ends: Vec<usize> = dnfa.find(haystack.as_bytes()).map(|m| m.end).collect();
+        assert_eq!(ends, dnfa_ends);
+        // "bca" ends in the state for "bca", the match for "a" is found through the failure links
+        let state = nfa.start_state();
+        let state = "bca".bytes().fold(state, |st, b| nfa.next_state(&st, &b));
+        assert!(nfa.has_match(&state, 1));
+        assert_eq!(nfa.get_match(&state, 1, 3).start, 2);
+
+        // the trie itself still only accepts the exact patterns
+        assert!(nfa.apply("xa".as_bytes()).is_empty());
+    }
+
+    #[test]
+    fn failure_links_cyclic() {
+        let mut nfa = NFA::from_dictionary(["ab", "b"]);
+        nfa.ignore_postfixes();
+        nfa.build_failure_links();
+        let failure_links = nfa.failure_links.as_ref().unwrap();
+        assert_eq!(failure_links.len(), nfa.states.len());
+        assert_eq!(failure_links[STUCK], STUCK);
+    }
+
+    #[test]
+    fn goto_function() {
+        let haystack = "xbabcaabcab bca caac".as_bytes();
+
+        let nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        let goto = nfa.compute_goto_function().unwrap();
+        let failure_links = nfa.compute_failure_links();
+        assert_eq!(goto.len(), nfa.states.len());
+
+        let mut dnfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        dnfa.ignore_prefixes();
+        let dnfa = dnfa.powerset_construction();
+
+        let mut state = START;
+        let mut dnfa_state = dnfa.start_state();
+        for &byte in haystack {
+            state = goto[state][byte as usize];
+            dnfa_state = dnfa.next_state(&dnfa_state, &byte);
+
+            let mut patts = BTreeSet::new();
+            let mut fallback = state;
+            loop {
+                patts.extend(&nfa.states[fallback].pattern_ends);
+                if fallback == START {
+                    break;
+                }
+                fallback = failure_links[fallback];
+            }
+            let dnfa_patts: BTreeSet<PatternNumber> = dnfa_state
+                .iter()
+                .flat_map(|&s| dnfa.states[s].pattern_ends.clone())
+                .collect();
+            assert_eq!(patts, dnfa_patts);
+        }
+    }
+
+    #[test]
+    fn goto_dnfa_sherlock() {
+        let haystack = HAYSTACK_SHERLOCK.as_bytes();
+        let needle_sets: &[&[&str]] = &[
+            &["Sherlock", "Street"],
+            &["Sherlock", "Holmes"],
+            &[
+                "Sherlock", "Holmes", "Watson", "Irene", "Adler", "John", "Baker",
+            ],
+            &["Sher", "Hol"],
+            &["Sherlock", "Holmes", "Watson"],
+        ];
+        for &needles in needle_sets {
+            let mut nfa = NFA::from_dictionary(needles);
+            let goto_dnfa = nfa.goto_dnfa().unwrap();
+            assert!(goto_dnfa
+                .states
+                .iter()
+                .all(|st| st.transitions.len() == 256
+                    && st.transitions.values().all(|to| to.len() == 1)));
+            nfa.ignore_prefixes();
+            let dfa = nfa.powerset_construction().into_dfa().unwrap();
+            let goto_dfa = goto_dnfa.into_dfa().unwrap();
+            assert!(goto_dfa.find(haystack).eq(dfa.find(haystack)));
+        }
+
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        assert_eq!(nfa.goto_dnfa().unwrap().apply(b"xbca"), vec![0, 4]);
+        nfa.ignore_prefixes();
+        assert!(!nfa.is_trie());
+        assert_eq!(nfa.goto_dnfa().err(), Some(ConversionError::NotATrie));
+
+        let mut nfa = NFA::from_dictionary(["ab", "b"]);
+        assert!(nfa.is_trie());
+        nfa.ignore_postfixes();
+        assert_eq!(
+            nfa.compute_goto_function().err(),
+            Some(ConversionError::NotATrie)
+        );
+    }
+
+    #[test]
+    fn failure_links_sherlock_alt1() {
+        let mut nfa = NFA::from_dictionary(vec!["Sherlock", "Street"]);
+        nfa.build_failure_links();
+
+        assert_eq!(158, nfa.find(HAYSTACK_SHERLOCK.as_bytes()).count());
+    }
 
     #[test]
     fn nfa_from_bench_sherlock_alt1() {
@@ -644,4 +4045,90 @@ mod tests {
 
         assert_eq!(count, dnfa.find(haystack.as_bytes()).count());
     }
+
+    #[test]
+    fn from_dot_round_trip() {
+        fn check(nfa: &NFA, inputs: &[&[u8]]) {
+            for &show_pattern_ends in &[false, true] {
+                let options = || DotOptions {
+                    show_pattern_ends,
+                    ..DotOptions::default()
+                };
+                let dot = nfa.dot(options());
+                let parsed = NFA::from_dot(&dot).unwrap();
+                assert_eq!(parsed.dot(options()), dot);
+                for input in nfa
+                    .dict
+                    .iter()
+                    .map(Vec::as_slice)
+                    .chain(inputs.iter().cloned())
+                {
+                    assert_eq!(parsed.apply(input), nfa.apply(input));
+                }
+            }
+        }
+
+        let inputs: &[&[u8]] = &[b"", b"b", b"abc", b"xbab", b"cab", b"\"\0\xff"];
+        let mut nfa = NFA::from_dictionary(BASIC_DICTIONARY);
+        nfa.set_state_name(2, "named \"state\"");
+        check(&nfa, inputs);
+        nfa.ignore_prefixes();
+        check(&nfa, inputs);
+        nfa.ignore_postfixes();
+        check(&nfa, inputs);
+        check(
+            &NFA::from_dictionary(["'\"", "\\", "a-z", "\t", "&<>"]),
+            inputs,
+        );
+
+        // Pseudo-random dictionaries of printable ASCII, without the ", " that separates them
+        let mut next = pseudo_random(1);
+        for _ in 0..10 {
+            let dict: Vec<Vec<u8>> = (0..1 + next() % 10)
+                .map(|_| {
+                    (0..1 + next() % 6)
+                        .map(|_| b"abcd\"'\\-~!"[next() % 10])
+                        .collect()
+                })
+                .collect();
+            let mut nfa = NFA::from_dictionary(&dict);
+            check(&nfa, inputs);
+            nfa.ignore_prefixes();
+            check(&nfa, inputs);
+        }
+    }
+
+    #[test]
+    fn from_dot_errors() {
+        let dot = NFA::from_dictionary(["ab"]).dot(DotOptions::default());
+        assert_eq!(
+            NFA::from_dot(&dot[..dot.len() - 1]).err(),
+            Some(DotParseError::UnexpectedEof)
+        );
+        assert_eq!(NFA::from_dot("").err(), Some(DotParseError::UnexpectedEof));
+        let duplicate = dot.replace("}", "    1 -> 2 [label=\"'a'\"];\n}");
+        assert_eq!(
+            NFA::from_dot(&duplicate).err(),
+            Some(DotParseError::DuplicateEdge {
+                from: 1,
+                to: 2,
+                byte: b'a'
+            })
+        );
+        let ranges = dot.replace("'a'", "'a'-");
+        assert_eq!(
+            NFA::from_dot(&ranges).err(),
+            Some(DotParseError::InvalidRangeSyntax("'a'-".to_string()))
+        );
+        let wide = dot.replace("'a'", "'\\u{100}'");
+        assert!(matches!(
+            NFA::from_dot(&wide),
+            Err(DotParseError::InvalidRangeSyntax(_))
+        ));
+        let line = dot.replace("1 -> 2", "1 => 2");
+        assert!(matches!(
+            NFA::from_dot(&line),
+            Err(DotParseError::InvalidLabel(_))
+        ));
+    }
 }
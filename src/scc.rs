@@ -0,0 +1,116 @@
+use std::cmp;
+
+/// Computes the strongly connected components of a graph with Tarjan's algorithm.
+/// States are numbered `0..num_states` and `successors` gives the outgoing edges of a state.
+/// The depth-first search keeps its own call stack instead of using recursion, so big automata
+///  don't overflow the stack.
+/// The components are returned in reverse topological order: a component comes before the
+///  components that have edges to it.
+pub fn tarjan_sccs<F: Fn(usize) -> Vec<usize>>(
+    num_states: usize,
+    successors: F,
+) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+
+    let mut index = vec![UNVISITED; num_states];
+    let mut lowlink = vec![0; num_states];
+    let mut on_stack = vec![false; num_states];
+    let mut scc_stack = Vec::new();
+    let mut sccs = Vec::new();
+    let mut next_index = 0;
+
+    for root in 0..num_states {
+        if index[root] != UNVISITED {
+            continue;
+        }
+
+        // Frames of the call stack: the state, its successors, and the next successor to visit
+        let mut call_stack = Vec::new();
+        macro_rules! visit {
+            ($state:expr) => {{
+                let state = $state;
+                index[state] = next_index;
+                lowlink[state] = next_index;
+                next_index += 1;
+                scc_stack.push(state);
+                on_stack[state] = true;
+                call_stack.push((state, successors(state), 0));
+            }};
+        }
+        visit!(root);
+
+        while let Some(frame) = call_stack.last_mut() {
+            let state = frame.0;
+            if let Some(&succ) = frame.1.get(frame.2) {
+                frame.2 += 1;
+                if index[succ] == UNVISITED {
+                    visit!(succ);
+                } else if on_stack[succ] {
+                    lowlink[state] = cmp::min(lowlink[state], index[succ]);
+                }
+                continue;
+            }
+
+            // All successors are done, so "return" from the visit of `state`
+            call_stack.pop();
+            if let Some(&(parent, _, _)) = call_stack.last() {
+                lowlink[parent] = cmp::min(lowlink[parent], lowlink[state]);
+            }
+            if lowlink[state] == index[state] {
+                let mut scc = Vec::new();
+                loop {
+                    let member = scc_stack.pop().unwrap();
+                    on_stack[member] = false;
+                    scc.push(member);
+                    if member == state {
+                        break;
+                    }
+                }
+                sccs.push(scc);
+            }
+        }
+    }
+
+    sccs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut sccs: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for scc in &mut sccs {
+            scc.sort_unstable();
+        }
+        sccs.sort();
+        sccs
+    }
+
+    #[test]
+    fn complete_graph() {
+        let sccs = tarjan_sccs(5, |_| (0..5).collect());
+        assert_eq!(sorted(sccs), vec![vec![0, 1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn linear_chain() {
+        let sccs = tarjan_sccs(4, |s| if s < 3 { vec![s + 1] } else { vec![] });
+        // reverse topological order: the end of the chain comes first
+        assert_eq!(sccs, vec![vec![3], vec![2], vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn disconnected_graph() {
+        let edges = [vec![1], vec![0], vec![3], vec![4], vec![2], vec![]];
+        let sccs = tarjan_sccs(edges.len(), |s| edges[s].clone());
+        assert_eq!(sorted(sccs), vec![vec![0, 1], vec![2, 3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn long_chain_does_not_overflow() {
+        let n = 1_000_000;
+        let sccs = tarjan_sccs(n, |s| if s + 1 < n { vec![s + 1] } else { vec![0] });
+        assert_eq!(sccs.len(), 1);
+        assert_eq!(sccs[0].len(), n);
+    }
+}
@@ -0,0 +1,112 @@
+use smallvec::SmallVec;
+use std::fmt;
+use std::iter::FromIterator;
+use std::slice;
+
+use crate::nfa::StateNumber;
+
+const INLINE: usize = 2;
+
+/// A sorted set of state numbers that keeps up to two elements inline, and only moves to the
+///  heap when it grows beyond that. The transitions of an NFA built from a dictionary nearly
+///  always have a single target state, which then doesn't need an allocation of its own.
+/// Only the parts of the `BTreeSet` API that the `NFA` uses are here.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SmallStateSet(SmallVec<[StateNumber; INLINE]>);
+
+impl SmallStateSet {
+    pub fn new() -> Self {
+        SmallStateSet(SmallVec::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, state: &StateNumber) -> bool {
+        self.0.binary_search(state).is_ok()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, StateNumber> {
+        self.0.iter()
+    }
+
+    /// Returns whether the state was new, like `BTreeSet::insert`
+    pub fn insert(&mut self, state: StateNumber) -> bool {
+        match self.0.binary_search(&state) {
+            Ok(_) => false,
+            Err(idx) => {
+                self.0.insert(idx, state);
+                true
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SmallStateSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl FromIterator<StateNumber> for SmallStateSet {
+    fn from_iter<I: IntoIterator<Item = StateNumber>>(iter: I) -> Self {
+        let mut set = SmallStateSet::new();
+        for state in iter {
+            set.insert(state);
+        }
+        set
+    }
+}
+
+impl<'a> IntoIterator for &'a SmallStateSet {
+    type Item = &'a StateNumber;
+    type IntoIter = slice::Iter<'a, StateNumber>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for SmallStateSet {
+    type Item = StateNumber;
+    type IntoIter = smallvec::IntoIter<[StateNumber; INLINE]>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn same_as_btreeset() {
+        let inputs = [5, 1, 3, 1, 9, 0, 5, 7];
+        let mut small = SmallStateSet::new();
+        let mut btree = BTreeSet::new();
+        for &state in &inputs {
+            assert_eq!(small.insert(state), btree.insert(state));
+            assert_eq!(small.len(), btree.len());
+            assert!(small.iter().eq(btree.iter()));
+        }
+        assert!(small.contains(&3));
+        assert!(!small.contains(&4));
+        assert!(small.0.spilled());
+        assert_eq!(format!("{:?}", small), format!("{:?}", btree));
+    }
+
+    #[test]
+    fn stays_inline() {
+        let set: SmallStateSet = vec![4, 2, 4].into_iter().collect();
+        assert!(!set.0.spilled());
+        assert_eq!(set.iter().cloned().collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![2, 4]);
+    }
+}
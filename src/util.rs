@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::nfa::{Input, PatternNumber, START};
+
+/// Shows a set of bytes as a list of ranges, e.g. `'a'-'c', 'x'`
+pub(crate) fn implode_ranges(bytes: BTreeSet<u8>) -> Vec<String> {
+    fn range(start: u8, end: u8) -> String {
+        if start == end {
+            format!("{:?}", start as char)
+        } else {
+            format!("{:?}-{:?}", start as char, end as char)
+        }
+    }
+
+    let mut res = Vec::new();
+    let mut i = bytes.iter();
+    if let Some(&start) = i.next() {
+        let mut start = start;
+        let mut prev = start;
+        for &cur in i {
+            if cur - 1 != prev {
+                res.push(range(start, prev));
+                start = cur;
+            }
+            prev = cur;
+        }
+        res.push(range(start, prev));
+        res
+    } else {
+        unreachable!();
+    }
+}
+
+/// Reads the ranges that `implode_ranges` shows back into bytes, in order. Gives `None` if the
+///  text isn't a list of ranges of chars up to `'\u{ff}'`.
+pub(crate) fn explode_ranges(ranges: &str) -> Option<Vec<u8>> {
+    // One char in the `Debug` format, with its quotes
+    fn char_literal(chars: &mut Peekable<Chars>) -> Option<u8> {
+        if chars.next()? != '\'' {
+            return None;
+        }
+        let c = match chars.next()? {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                c @ ('\\' | '\'' | '"') => c,
+                'u' => {
+                    if chars.next()? != '{' {
+                        return None;
+                    }
+                    let mut hex = String::new();
+                    loop {
+                        match chars.next()? {
+                            '}' => break,
+                            c => hex.push(c),
+                        }
+                    }
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                _ => return None,
+            },
+            c => c,
+        };
+        if chars.next()? != '\'' || c as u32 > 0xff {
+            return None;
+        }
+        Some(c as u8)
+    }
+
+    let mut bytes = Vec::new();
+    let mut chars = ranges.chars().peekable();
+    loop {
+        let start = char_literal(&mut chars)?;
+        let end = if chars.peek() == Some(&'-') {
+            chars.next();
+            char_literal(&mut chars)?
+        } else {
+            start
+        };
+        if end < start {
+            return None;
+        }
+        bytes.extend(start..=end);
+        match chars.next() {
+            None => return Some(bytes),
+            Some(',') if chars.next() == Some(' ') => {}
+            _ => return None,
+        }
+    }
+}
+
+/// The opening of a DOT graph, labeled with `label`, or with the dictionary if there is none
+pub(crate) fn dot_header(out: &mut String, dict: &[Vec<Input>], label: &Option<String>) {
+    let label = match label {
+        Some(label) => label.clone(),
+        None => dict
+            .iter()
+            .map(|x| String::from_utf8_lossy(x.as_ref()).into_owned())
+            .collect::<Vec<String>>()
+            .join(", "),
+    };
+    // The label is HTML-like, so it needs these escaped
+    let label = label
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    write!(
+        out,
+        r#"
+digraph automaton {{
+    label=<<FONT POINT-SIZE="20">{}</FONT>>;
+    labelloc="l";
+    labeljust="l";
+    rankdir="LR";
+    start [shape="none", label="", width=0];
+    start -> {};
+"#,
+        label, START
+    )
+    .unwrap();
+}
+
+/// The attribute that shows the pattern ends of a final state next to it
+pub(crate) fn dot_pattern_ends(pattern_ends: &[PatternNumber]) -> String {
+    let pattern_ends: Vec<String> = pattern_ends.iter().map(|p| p.to_string()).collect();
+    format!(" [xlabel=\"{}\"]", pattern_ends.join(", "))
+}
+
+/// A linear congruential generator, for tests that need the same pseudo-random input every run
+#[cfg(test)]
+pub(crate) fn pseudo_random(seed: u32) -> impl FnMut() -> usize {
+    let mut seed = seed;
+    move || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (seed >> 16) as usize
+    }
+}